@@ -0,0 +1,39 @@
+use super::{MoveKind, TopOut, piece::Kind, stats::TSpin};
+
+/// Something that happened in the engine, for front ends, audio, stats or replays to react to.
+/// Queued up as they happen, until drained with [`Engine::drain_events`](super::Engine::drain_events).
+#[derive(Clone, PartialEq, Debug)]
+pub enum EngineEvent {
+    /// A new piece appeared at the top of the matrix.
+    PieceSpawned(Kind),
+    /// The cursor shifted a column.
+    Moved(MoveKind),
+    /// The cursor turned.
+    Rotated {
+        /// Index of the kick used, 0 being none.
+        kick: usize,
+    },
+    /// The cursor locked into the stack.
+    Locked(Kind),
+    /// A piece cleared lines, or was a T-spin that didn't.
+    LinesCleared {
+        /// The rows cleared, from the bottom up, before anything above them fell.
+        rows: Vec<usize>,
+        /// What kind of T-spin the piece was, if any.
+        tspin: TSpin,
+        /// How many clears in a row this makes, after the first.
+        combo: Option<u32>,
+        /// Whether this continued a run of tetrises and T-spins, for the bonus.
+        back_to_back: bool,
+        /// How many clears came before this one from the same piece, through falling stack.
+        chain: u32,
+    },
+    /// The clear left the matrix empty.
+    PerfectClear,
+    /// The cursor was put into the hold.
+    Hold(Kind),
+    /// The level went up, to this one.
+    LevelUp(u32),
+    /// The game ended.
+    TopOut(TopOut),
+}
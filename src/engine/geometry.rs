@@ -1,27 +1,19 @@
-use super::{Coordinate, Matrix};
+use super::Coordinate;
 
-pub trait GridIncrement: Sized {
+pub trait GridIncrement {
     type Width;
-    const WIDTH: Self::Width;
 
-    fn grid_incd(mut self) -> Self {
-        self.grid_inc();
-        self
-    }
-
-    fn grid_inc(&mut self);
+    fn grid_inc(&mut self, width: Self::Width);
 }
 
 impl GridIncrement for Coordinate {
     type Width = usize;
-    const WIDTH: Self::Width = Matrix::WIDTH;
 
-    fn grid_inc(&mut self) {
+    fn grid_inc(&mut self, width: Self::Width) {
         self.x += 1;
-        self.x %= Matrix::WIDTH;
+        self.x %= width;
         if self.x == 0 {
             self.y += 1;
         }
     }
 }
-
@@ -0,0 +1,172 @@
+use serde::{Serialize, Deserialize};
+use super::{Coordinate, Matrix};
+
+/// What happens to the stack above rows that get cleared.
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub enum LineClearGravity {
+    /// Everything above moves down by the number of rows cleared, even if that leaves it floating.
+    Naive,
+    /// Connected groups of cells fall as a unit until they land on something.
+    Sticky,
+    /// Every cell falls on its own, like sand.
+    Sand,
+}
+
+impl Matrix {
+    /// Empties the given rows and lets what's left fall according to `gravity`.
+    /// With anything but naive gravity, that can fill more rows.
+    pub(super) fn clear_lines_with(&mut self, rows: &[usize], gravity: LineClearGravity) {
+        let settle = match gravity {
+            LineClearGravity::Naive => return self.clear_lines(rows),
+            LineClearGravity::Sticky => Self::settle_sticky,
+            LineClearGravity::Sand => Self::settle_sand,
+        };
+
+        for &row in rows {
+            self.cells[row * self.width..(row + 1) * self.width].fill(None);
+        }
+        settle(self);
+    }
+
+    fn settle_sand(&mut self) {
+        for x in 0..self.width {
+            let mut landing = 0;
+            for y in 0..self.height() {
+                let coord = Coordinate::new(x, y);
+                if let Some(color) = self[coord].take() {
+                    self[Coordinate::new(x, landing)] = Some(color);
+                    landing += 1;
+                }
+            }
+        }
+    }
+
+    fn settle_sticky(&mut self) {
+        // Landing groups can join up with the ones above, so go round again until nothing moves
+        let mut moved = true;
+        while moved {
+            moved = false;
+            for group in self.groups() {
+                let mut fall = 0;
+                while group.iter().all(|coord| {
+                    coord.y > fall && {
+                        let below = Coordinate::new(coord.x, coord.y - fall - 1);
+                        group.contains(&below) || self[below].is_none()
+                    }
+                }) {
+                    fall += 1;
+                }
+                if fall == 0 {
+                    continue;
+                }
+
+                let colors = group.iter().map(|&coord| self[coord].take()).collect::<Vec<_>>();
+                for (coord, color) in group.iter().zip(colors) {
+                    self[Coordinate::new(coord.x, coord.y - fall)] = color;
+                }
+                moved = true;
+            }
+        }
+    }
+
+    /// Groups of filled cells connected edge to edge, lowest first.
+    fn groups(&self) -> Vec<Vec<Coordinate>> {
+        let mut grouped = vec![false; self.cells.len()];
+        let mut groups = Vec::new();
+
+        for start in (0..self.cells.len()).map(|index| Coordinate::new(index % self.width, index / self.width)) {
+            if self[start].is_none() || grouped[self.indexing(start)] {
+                continue;
+            }
+
+            grouped[self.indexing(start)] = true;
+            let mut group = vec![start];
+            let mut next = 0;
+            while let Some(&coord) = group.get(next) {
+                next += 1;
+                let neighbours = [
+                    coord.x.checked_sub(1).map(|x| Coordinate::new(x, coord.y)),
+                    Some(Coordinate::new(coord.x + 1, coord.y)),
+                    coord.y.checked_sub(1).map(|y| Coordinate::new(coord.x, y)),
+                    Some(Coordinate::new(coord.x, coord.y + 1)),
+                ];
+                for neighbour in neighbours.into_iter().flatten() {
+                    if self.is_occupied(neighbour) && !grouped[self.indexing(neighbour)] {
+                        grouped[self.indexing(neighbour)] = true;
+                        group.push(neighbour);
+                    }
+                }
+            }
+            groups.push(group);
+        }
+
+        // Scanning from the bottom row up finds each group by its lowest cell, so they're already in order
+        groups
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use super::super::test::matrix_from;
+
+    fn rows_of(matrix: &Matrix, count: usize) -> Vec<String> {
+        (0..count).rev()
+            .map(|y| (0..matrix.width())
+                .map(|x| if matrix[Coordinate::new(x, y)].is_some() { '#' } else { '.' })
+                .collect()
+            )
+            .collect()
+    }
+
+    const STACK: [&str;4] = [
+        ".##.......",
+        "..#..#....",
+        "##########",
+        "#.#.......",
+    ];
+
+    #[test]
+    fn naive_leaves_cells_floating() {
+        let mut matrix = matrix_from(&STACK);
+        matrix.clear_lines_with(&[1], LineClearGravity::Naive);
+        assert_eq!(rows_of(&matrix, 3), [
+            ".##.......",
+            "..#..#....",
+            "#.#.......",
+        ]);
+    }
+
+    #[test]
+    fn sticky_groups_fall_together() {
+        let mut matrix = matrix_from(&STACK);
+        matrix.clear_lines_with(&[1], LineClearGravity::Sticky);
+        assert_eq!(rows_of(&matrix, 3), [
+            ".##.......",
+            "..#.......",
+            "#.#..#....",
+        ]);
+    }
+
+    #[test]
+    fn sand_falls_cell_by_cell() {
+        let mut matrix = matrix_from(&STACK);
+        matrix.clear_lines_with(&[1], LineClearGravity::Sand);
+        assert_eq!(rows_of(&matrix, 3), [
+            "..#.......",
+            "..#.......",
+            "###..#....",
+        ]);
+    }
+
+    #[test]
+    fn groups_are_edge_connected() {
+        let matrix = matrix_from(&[
+            "#.#",
+            "#.##",
+            ".#..",
+        ]);
+        let sizes = matrix.groups().iter().map(Vec::len).collect::<Vec<_>>();
+        assert_eq!(sizes, [1, 2, 3]);
+    }
+}
@@ -0,0 +1,75 @@
+use std::time::Duration;
+use serde::{Serialize, Deserialize};
+
+/// What buys a grounded piece more time before it locks.
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub enum LockMode {
+    /// Moving or rotating restarts the timer, up to 15 times.
+    /// Reaching a new lowest row refunds all of them.
+    ExtendedPlacement,
+    /// Moving or rotating always restarts the timer.
+    Infinite,
+    /// Only falling to a lower row restarts the timer.
+    StepReset,
+}
+
+/// Tracks how long the cursor has been sitting on the stack.
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub(super) struct LockDelay {
+    elapsed: Duration,
+    resets: u32,
+    lowest_row: isize,
+    touched_down: bool,
+}
+
+impl LockDelay {
+    pub const MAX_RESETS: u32 = 15;
+
+    pub fn new(row: isize) -> Self {
+        Self {
+            elapsed: Duration::ZERO,
+            resets: 0,
+            lowest_row: row,
+            touched_down: false,
+        }
+    }
+
+    /// The cursor was successfully moved or rotated.
+    pub fn on_manipulate(&mut self, mode: LockMode) {
+        match mode {
+            LockMode::ExtendedPlacement => if self.touched_down && self.resets < Self::MAX_RESETS {
+                self.resets += 1;
+                self.elapsed = Duration::ZERO;
+            }
+            LockMode::Infinite => self.elapsed = Duration::ZERO,
+            LockMode::StepReset => {}
+        }
+    }
+
+    /// The cursor moved down to `row`.
+    pub fn on_fall(&mut self, row: isize, mode: LockMode) {
+        match mode {
+            LockMode::ExtendedPlacement => if row < self.lowest_row {
+                *self = Self::new(row);
+            }
+            LockMode::Infinite | LockMode::StepReset => {
+                self.elapsed = Duration::ZERO;
+                self.lowest_row = self.lowest_row.min(row);
+            }
+        }
+    }
+
+    /// Advances the timer if the cursor is resting on something,
+    /// and returns whether it's time to lock.
+    pub fn advance(&mut self, dt: Duration, grounded: bool, delay: Duration, mode: LockMode) -> bool {
+        if !grounded {
+            return false;
+        }
+
+        self.touched_down = true;
+        self.elapsed += dt;
+
+        let out_of_resets = mode == LockMode::ExtendedPlacement && self.resets >= Self::MAX_RESETS;
+        out_of_resets || self.elapsed >= delay
+    }
+}
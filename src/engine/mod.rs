@@ -1,16 +1,54 @@
-use std::{ops::{Index, IndexMut, Range}, time::Duration, slice::ArrayChunks};
+use std::{fmt, ops::{Index, IndexMut}, time::Duration, slice::ChunksExact, sync::Arc, collections::VecDeque};
 use cgmath::EuclideanSpace;
-use rand::{prelude::{SliceRandom, ThreadRng}, thread_rng};
-use self::{piece::{Piece, Kind as PieceKind, Rotation}, geometry::GridIncrement};
+use serde::{Serialize, Deserialize};
+use self::{
+    piece::{Piece, Kind as PieceKind, Rotation},
+    geometry::GridIncrement,
+    rotation_system::{RotationSystem, Srs},
+    randomizer::{Randomizer, Bag},
+    lock_delay::LockDelay,
+    rules::{Rules, SoftDrop},
+    stats::{Stats, TSpin},
+};
 
+pub use self::{
+    lock_delay::LockMode, event::EngineEvent, phase::Phase, gravity::LineClearGravity,
+    snapshot::{Snapshot, SnapshotError}, undo::UndoError,
+};
+
+/// Tetrominoes and their positions.
 pub mod piece;
+/// How pieces are shaped and kicked when they turn.
+pub mod rotation_system;
+/// The order pieces are dealt in.
+pub mod randomizer;
+/// Settings for how a game plays.
+pub mod rules;
+/// Scoring and levels.
+pub mod stats;
+/// Recording games and playing them back.
+pub mod replay;
+mod snapshot;
+mod undo;
+mod event;
+mod phase;
+mod gravity;
 mod geometry;
+mod lock_delay;
 
-type Coordinate = cgmath::Point2<usize>;
-type Offset = cgmath::Vector2<isize>;
+/// A cell on the matrix, counting up from the bottom left.
+pub type Coordinate = cgmath::Point2<usize>;
+/// A piece position or displacement, which can go off the matrix.
+pub type Offset = cgmath::Vector2<isize>;
 
-#[derive(Clone, Copy, PartialEq, Debug)]
-pub enum MoveKind { Left, Right }
+/// A sideways shift of one column.
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub enum MoveKind {
+    /// Towards column 0.
+    Left,
+    /// Away from column 0.
+    Right,
+}
 
 impl MoveKind {
     fn offset(&self) -> Offset {
@@ -21,25 +59,126 @@ impl MoveKind {
     }
 }
 
+/// A turn of the cursor, by a quarter either way or a half.
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub enum RotateKind {
+    /// A quarter turn clockwise.
+    Clockwise,
+    /// A quarter turn counter clockwise.
+    CounterClockwise,
+    /// A half turn, which SRS does without kicks.
+    Half,
+}
+
+impl RotateKind {
+    fn apply(&self, rotation: Rotation) -> Rotation {
+        match self {
+            RotateKind::Clockwise => rotation.clockwise(),
+            RotateKind::CounterClockwise => rotation.counter_clockwise(),
+            RotateKind::Half => rotation.clockwise().clockwise(),
+        }
+    }
+}
+
+/// Nanoseconds per row at each level from 1, following the guideline's (0.8 - (level - 1) * 0.007) ^ (level - 1)
+/// seconds. Worked out ahead of time so every build agrees to the nanosecond, since replays depend on it.
+/// Past level 31 gravity is over 20G anyway and the curve stops making sense.
+const DROP_TIMES: [u64;31] = [
+    /*  1 */ 1000000000, 793000000, 617796000, 472729139, 355196928,
+    /*  6 */ 262003550, 189677245, 134734731, 93882249, 64151585,
+    /* 11 */ 42976258, 28217678, 18153329, 11439342, 7058616,
+    /* 16 */ 4263557, 2520084, 1457139, 823907, 455398,
+    /* 21 */ 245968, 129771, 66852, 33614, 16490,
+    /* 26 */ 7889, 3679, 1672, 740, 319,
+    /* 31 */ 134,
+];
+
+/// Gravity is capped at 20G: twenty rows every 60th of a second.
+const MIN_DROP_TIME: Duration = Duration::from_nanos(1_000_000_000 / 60 / 20);
+
+/// Ways the game can end.
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub enum TopOut {
+    /// The next piece overlapped the stack where it spawned.
+    BlockOut,
+    /// A piece locked with cells above the top of the matrix.
+    LockOut,
+}
+
+/// Why the cursor couldn't be moved or rotated.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum MoveError {
+    /// There's no piece in play, between pieces or after topping out.
+    NoCursor,
+    /// Every position tried overlapped the stack or the walls.
+    Blocked,
+}
+
+impl fmt::Display for MoveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MoveError::NoCursor => write!(f, "No piece in play"),
+            MoveError::Blocked => write!(f, "Piece is blocked"),
+        }
+    }
+}
+
+/// A single game, with no rendering or input of its own.
+///
+/// Call [`Engine::update`] with the time that's passed, feed it the player's moves,
+/// and read back what happened through [`Engine::drain_events`].
+#[derive(Clone)]
 pub struct Engine {
     matrix: Matrix,
-    bag: Vec<PieceKind>,
-    rng: ThreadRng,
+    randomizer: Box<dyn Randomizer>,
+    queue: VecDeque<PieceKind>,
+    rotation_system: Arc<dyn RotationSystem>,
+    rules: Rules,
     cursor: Option<Piece>,
-    level: u8,
+    /// The kick used, if the cursor's last successful move was a rotation.
+    last_rotation: Option<usize>,
+    lock_delay: LockDelay,
+    gravity_progress: Duration,
+    soft_dropping: bool,
+    held: Option<PieceKind>,
+    hold_used: bool,
+    /// Never [`Phase::Locking`], which is worked out from the cursor when asked for.
+    phase: Phase,
+    stats: Stats,
+    events: VecDeque<EngineEvent>,
+    history: undo::History,
+}
+
+impl Default for Engine {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Engine {
+    /// A standard game: a 10 by 20 matrix, SRS, a 7-bag and the default rules.
     pub fn new() -> Self {
         Engine {
             matrix: Matrix::blank(),
-            bag: Vec::new(),
-            rng: thread_rng(),
+            randomizer: Box::new(Bag::seven(rand::random())),
+            queue: VecDeque::new(),
+            rotation_system: Arc::new(Srs),
+            rules: Rules::default(),
             cursor: None,
-            level: 1,
+            last_rotation: None,
+            lock_delay: LockDelay::new(0),
+            gravity_progress: Duration::ZERO,
+            soft_dropping: false,
+            held: None,
+            hold_used: false,
+            phase: Phase::EntryDelay { elapsed: Duration::ZERO },
+            stats: Stats::default(),
+            events: VecDeque::new(),
+            history: undo::History::default(),
         }
     }
 
+    /// A standard game starting on `matrix`, which can have cells filled in already.
     pub fn with_matrix(matrix: Matrix) -> Self {
         Self {
             matrix,
@@ -47,147 +186,585 @@ impl Engine {
         }
     }
 
-    fn refill_bag(&mut self) {
-        debug_assert!(self.bag.is_empty());
-        self.bag.extend_from_slice(PieceKind::ALL.as_slice());
-        self.bag.shuffle(&mut self.rng);
+    /// A standard game with pieces shaped and kicked by `rotation_system`.
+    pub fn with_rotation_system(rotation_system: impl RotationSystem + 'static) -> Self {
+        Self {
+            rotation_system: Arc::new(rotation_system),
+            ..Self::new()
+        }
+    }
+
+    /// A standard game played under `rules`.
+    pub fn with_rules(rules: Rules) -> Self {
+        Self {
+            rules,
+            ..Self::new()
+        }
+    }
+
+    /// A standard game with pieces dealt by `randomizer`.
+    pub fn with_randomizer(randomizer: impl Randomizer + 'static) -> Self {
+        Self {
+            randomizer: Box::new(randomizer),
+            ..Self::new()
+        }
+    }
+
+    /// Deals the next piece and puts it at the top of the matrix. There mustn't be a cursor already.
+    pub(crate) fn spawn_next(&mut self) -> Result<(), TopOut> {
+        let kind = self.queue
+            .pop_front()
+            .unwrap_or_else(|| self.randomizer.next());
+        self.spawn(kind)
+    }
+
+    /// The next `count` pieces to be dealt, dealing more into the queue as needed.
+    pub fn preview(&mut self, count: usize) -> impl Iterator<Item = PieceKind> + '_ {
+        while self.queue.len() < count {
+            self.queue.push_back(self.randomizer.next());
+        }
+        self.queue.iter().copied().take(count)
     }
 
-    fn place_cursor(&mut self) {
+    fn spawn(&mut self, kind: PieceKind) -> Result<(), TopOut> {
+        debug_assert!(self.cursor.is_none(), "Spawned a piece over an existing cursor");
+
+        let rotation = self.rotation_system.spawn_rotation(kind);
+        let lowest_cell = self.rotation_system
+            .shape(kind, rotation)
+            .into_iter()
+            .map(|cell| cell.y)
+            .min()
+            .unwrap();
+
+        let position = Offset::new(
+            (self.matrix.width as isize - kind.grid_size()) / 2,
+            self.matrix.visible_height as isize - lowest_cell,
+        );
+        let piece = Piece { kind, rotation, position };
+
+        if self.matrix.is_clipping(&piece, &*self.rotation_system) {
+            return Err(self.top_out(TopOut::BlockOut));
+        }
+        self.cursor = Some(piece);
+        self.phase = Phase::Falling;
+        self.events.push_back(EngineEvent::PieceSpawned(kind));
+        self.lock_delay = LockDelay::new(position.y);
+        self.gravity_progress = Duration::ZERO;
+        self.last_rotation = None;
+
+        // Pieces spawn just above the visible rows, then drop in straight away if there's room
+        self.tick_down();
+
+        Ok(())
+    }
+
+    fn place_cursor(&mut self) -> Result<(), TopOut> {
+        self.on_piece_end();
         let cursor = self.cursor.take().expect("Called place_cursor without a cursor");
 
         debug_assert!(
-            self.matrix.is_placeable(&cursor),
+            !self.matrix.is_clipping(&cursor, &*self.rotation_system),
             "Tried to place cursor in an unplaceable location: {:?}",
             cursor
         );
 
+        // Locking entirely out of sight is a top out, as is poking out the top of the buffer
+        let above_visible = cursor.cells(&*self.rotation_system)
+            .is_none_or(|cells| cells.iter().all(|coord| coord.y >= self.matrix.visible_height));
+        if above_visible || !self.matrix.is_placeable(&cursor, &*self.rotation_system) {
+            return Err(self.top_out(TopOut::LockOut));
+        }
+
+        let tspin = self.tspin(&cursor);
+
         let color = cursor.kind.color();
-        for coord in cursor.cells().unwrap() {
+        for coord in cursor.cells(&*self.rotation_system).unwrap() {
             self.matrix[coord] = Some(color);
         }
+        self.hold_used = false;
+        self.events.push_back(EngineEvent::Locked(cursor.kind));
+
+        self.score_clear(tspin, 0);
+
+        // Either delay might be zero
+        self.advance_delays(Duration::ZERO)
+    }
+
+    /// Scores any full rows, after a piece locks or a chain reaction fills them,
+    /// and waits to clear them. Chain reactions score as line clears continuing the combo.
+    fn score_clear(&mut self, tspin: TSpin, chain: u32) {
+        let rows = self.matrix.full_lines();
+        let perfect_clear = self.matrix.lines()
+            .enumerate()
+            .all(|(row, line)| rows.contains(&row) || line.iter().all(Option::is_none));
+        let award = self.stats.award_lock(rows.len(), tspin, perfect_clear, self.rules.goal);
+
+        if !rows.is_empty() || tspin != TSpin::None {
+            self.events.push_back(EngineEvent::LinesCleared {
+                rows: rows.clone(),
+                tspin,
+                combo: award.combo,
+                back_to_back: award.back_to_back,
+                chain,
+            });
+        }
+        if award.perfect_clear {
+            self.events.push_back(EngineEvent::PerfectClear);
+        }
+        if award.level_up {
+            self.events.push_back(EngineEvent::LevelUp(self.stats.level));
+        }
+
+        self.phase = if rows.is_empty() {
+            Phase::EntryDelay { elapsed: Duration::ZERO }
+        } else {
+            Phase::LineClearDelay { rows, elapsed: Duration::ZERO, chain }
+        };
+    }
+
+    /// Runs down the line clear and entry delays, clearing rows and spawning the next piece as they end.
+    fn advance_delays(&mut self, mut dt: Duration) -> Result<(), TopOut> {
+        loop {
+            match &mut self.phase {
+                Phase::LineClearDelay { rows, elapsed, chain } => {
+                    *elapsed += dt;
+                    if *elapsed < self.rules.line_clear_delay {
+                        return Ok(());
+                    }
+                    dt = *elapsed - self.rules.line_clear_delay;
+
+                    let (rows, chain) = (std::mem::take(rows), *chain);
+                    self.matrix.clear_lines_with(&rows, self.rules.line_clear_gravity);
+                    // Whatever fell might have filled more rows, but if not there's nothing to score
+                    if self.matrix.full_lines().is_empty() {
+                        self.phase = Phase::EntryDelay { elapsed: Duration::ZERO };
+                    } else {
+                        self.score_clear(TSpin::None, chain + 1);
+                    }
+                }
+                Phase::EntryDelay { elapsed } => {
+                    *elapsed += dt;
+                    if *elapsed < self.rules.entry_delay {
+                        return Ok(());
+                    }
+                    self.spawn_next()?;
+                    self.on_piece_start();
+                    return Ok(());
+                }
+                _ => return Ok(()),
+            }
+        }
     }
 
-    pub fn move_cursor(&mut self, kind: MoveKind) -> Result<(), ()> {
-        let Some(cursor) = self.cursor.as_mut() else {
+    /// What the game is doing right now.
+    pub fn phase(&self) -> Phase {
+        match &self.phase {
+            Phase::Falling if self.cursor_has_hit_bottom() => Phase::Locking,
+            phase => phase.clone(),
+        }
+    }
+
+    fn top_out(&mut self, top_out: TopOut) -> TopOut {
+        // Ends the piece in play for undo, whether it couldn't lock or a hold couldn't spawn its replacement
+        self.on_piece_end();
+        self.phase = Phase::ToppedOut(top_out);
+        self.events.push_back(EngineEvent::TopOut(top_out));
+        top_out
+    }
+
+    /// Takes everything that's happened since the last call, oldest first.
+    pub fn drain_events(&mut self) -> impl Iterator<Item = EngineEvent> + '_ {
+        self.events.drain(..)
+    }
+
+    /// Classifies a T about to lock using the 3-corner rule: three of the four
+    /// cells diagonal to its centre must be filled, and the last move a rotation.
+    /// It's a full T-spin if both corners it points towards are filled, or if it
+    /// got there with the rotation system's upgrading kick (as in an SRS T-spin triple or fin),
+    /// otherwise a mini.
+    fn tspin(&self, piece: &Piece) -> TSpin {
+        let (PieceKind::T, Some(kick)) = (piece.kind, self.last_rotation) else {
+            return TSpin::None;
+        };
+
+        let shape = self.rotation_system.shape(piece.kind, piece.rotation);
+        let adjacent = |a: Offset, b: Offset| (a.x - b.x).abs() + (a.y - b.y).abs() == 1;
+        let Some(&center) = shape.iter().find(|&&cell|
+            shape.iter().filter(|&&other| adjacent(cell, other)).count() == 3
+        ) else {
+            return TSpin::None;
+        };
+        let Some(&nub) = shape.iter().find(|&&cell|
+            cell != center && !shape.contains(&(center * 2 - cell))
+        ) else {
+            return TSpin::None;
+        };
+        let facing = nub - center;
+
+        // Walls and floor count as filled, open air above the matrix doesn't
+        let filled = |corner: Offset| match (piece.position + corner).cast::<usize>() {
+            Some(coord) => {
+                let coord = Coordinate::from_vec(coord);
+                !self.matrix.valid_coord(coord) || self.matrix.is_occupied(coord)
+            }
+            None => true,
+        };
+
+        let corners = [(-1, -1), (-1, 1), (1, -1), (1, 1)].map(Offset::from);
+        let filled_corners = corners.iter().filter(|&&corner| filled(center + corner)).count();
+        let filled_front = corners.iter()
+            .filter(|&&corner| corner.x * facing.x + corner.y * facing.y > 0 && filled(center + corner))
+            .count();
+
+        match (filled_corners, filled_front) {
+            (0..=2, _) => TSpin::None,
+            (_, 2) => TSpin::Full,
+            _ if self.rotation_system.full_tspin_kick() == Some(kick) => TSpin::Full,
+            _ => TSpin::Mini,
+        }
+    }
+
+    /// Swaps the cursor for the held piece, or for the next piece if nothing is held yet.
+    /// This can only be done once per piece, until it locks.
+    pub fn hold(&mut self) -> Result<(), TopOut> {
+        if self.hold_used {
+            return Ok(());
+        }
+        let Some(cursor) = self.cursor.take() else {
             return Ok(());
         };
 
+        self.hold_used = true;
+        self.events.push_back(EngineEvent::Hold(cursor.kind));
+        match self.held.replace(cursor.kind) {
+            Some(kind) => self.spawn(kind),
+            None => self.spawn_next(),
+        }
+    }
+
+    /// The piece put aside by [`Engine::hold`], if any.
+    pub fn held_piece(&self) -> Option<PieceKind> {
+        self.held
+    }
+
+    /// Whether the current piece has already been swapped with the hold.
+    pub fn hold_used(&self) -> bool {
+        self.hold_used
+    }
+
+    /// Cells of a piece as it spawns, relative to the bottom left of its bounding box.
+    pub fn spawn_shape(&self, kind: PieceKind) -> [Offset;Piece::CELL_COUNT] {
+        let rotation = self.rotation_system.spawn_rotation(kind);
+        self.rotation_system.shape(kind, rotation)
+    }
+
+    /// Shifts the cursor a column sideways, if there's room.
+    pub fn move_cursor(&mut self, kind: MoveKind) -> Result<(), MoveError> {
+        let cursor = self.cursor.ok_or(MoveError::NoCursor)?;
         let new = cursor.moved_by(kind.offset());
 
-        if self.matrix.is_clipping(&new) {
-            return Err(());
+        if self.matrix.is_clipping(&new, &*self.rotation_system) {
+            return Err(MoveError::Blocked);
         }
 
         self.cursor = Some(new);
+        self.last_rotation = None;
+        self.lock_delay.on_manipulate(self.rules.lock_mode);
+        self.events.push_back(EngineEvent::Moved(kind));
         Ok(())
     }
 
+    /// Rotates the cursor, trying each of the rotation system's kicks in order.
+    /// On success, returns the index of the kick that was used (0 being no kick).
+    pub fn rotate_cursor(&mut self, kind: RotateKind) -> Result<usize, MoveError> {
+        let cursor = self.cursor.ok_or(MoveError::NoCursor)?;
+
+        let rotated = Piece {
+            rotation: kind.apply(cursor.rotation),
+            ..cursor
+        };
+
+        let kicks = self.rotation_system.kicks(&self.matrix, &cursor, rotated.rotation);
+        for (index, kick) in kicks.into_iter().enumerate() {
+            let new = rotated.moved_by(kick);
+            if !self.matrix.is_clipping(&new, &*self.rotation_system) {
+                self.cursor = Some(new);
+                self.last_rotation = Some(index);
+                self.lock_delay.on_manipulate(self.rules.lock_mode);
+                self.events.push_back(EngineEvent::Rotated { kick: index });
+                return Ok(index);
+            }
+        }
+
+        Err(MoveError::Blocked)
+    }
+
+    /// The piece under the player's control, if there is one right now.
+    pub fn cursor(&self) -> Option<Piece> {
+        self.cursor
+    }
+
+    /// The rotation system pieces are shaped and kicked by, for working out their cells.
+    pub fn rotation_system(&self) -> &dyn RotationSystem {
+        &*self.rotation_system
+    }
+
+    /// The cursor's cells and colour, for drawing it.
     pub fn cursor_info(&self) -> Option<([Coordinate;Piece::CELL_COUNT], Color)> {
         let cursor = self.cursor?;
         Some((
-            cursor.cells().unwrap(),
+            cursor.cells(&*self.rotation_system).unwrap(),
             cursor.kind.color(),
         ))
     }
 
-    pub fn DEBUG_test_cursor(&mut self, kind: PieceKind, position: Offset) {
-        let piece = Piece { kind, rotation: Rotation::N, position };
-        self.cursor = Some(piece);
+    /// Where the cursor would land if hard dropped now.
+    pub fn ghost_info(&self) -> Option<([Coordinate;Piece::CELL_COUNT], Color)> {
+        let mut ghost = self.cursor?;
+        loop {
+            let new = ghost.moved_by(Offset::new(0, -1));
+            if self.matrix.is_clipping(&new, &*self.rotation_system) {
+                break;
+            }
+            ghost = new;
+        }
+        Some((
+            ghost.cells(&*self.rotation_system).unwrap(),
+            ghost.kind.color(),
+        ))
     }
 
-    fn tick_down(&mut self) {
-        self.cursor = Some(self.ticked_down_cursor().unwrap());
+    /// Moves the cursor down a row if there's room, returning whether it moved.
+    fn tick_down(&mut self) -> bool {
+        let Some(new) = self.ticked_down_cursor() else {
+            return false;
+        };
+
+        self.cursor = Some(new);
+        self.last_rotation = None;
+        self.lock_delay.on_fall(new.position.y, self.rules.lock_mode);
+        true
     }
 
+    /// Runs the game forward by `dt`: the cursor falls under gravity and locks
+    /// if it's been resting on the stack for too long, or the delays after a lock count down.
+    pub fn update(&mut self, dt: Duration) -> Result<(), TopOut> {
+        match self.phase {
+            Phase::ToppedOut(top_out) => Err(top_out),
+            Phase::Falling | Phase::Locking => {
+                self.apply_gravity(dt);
+                self.advance_lock_delay(dt).map(drop)
+            }
+            Phase::LineClearDelay { .. } | Phase::EntryDelay { .. } => self.advance_delays(dt),
+        }
+    }
+
+    /// Starts or stops soft dropping. Soft dropping stays on until it's turned off.
+    pub fn set_soft_drop(&mut self, active: bool) {
+        self.soft_dropping = active;
+    }
+
+    /// Score, level, lines and the rest of the game's progress.
+    pub fn stats(&self) -> &Stats {
+        &self.stats
+    }
+
+    /// The rules the game is being played under.
+    pub fn rules(&self) -> &Rules {
+        &self.rules
+    }
+
+    fn apply_gravity(&mut self, dt: Duration) {
+        let drop_time = match (self.soft_dropping, self.rules.soft_drop) {
+            (false, _) => self.drop_time(),
+            (true, SoftDrop::Factor(factor)) => self.drop_time() / factor.max(1),
+            (true, SoftDrop::Sonic) => {
+                while self.tick_down() {
+                    self.stats.award_soft_drop(1);
+                }
+                self.gravity_progress = Duration::ZERO;
+                return;
+            }
+        }.max(MIN_DROP_TIME);
+
+        self.gravity_progress += dt;
+        while self.gravity_progress >= drop_time {
+            self.gravity_progress -= drop_time;
+            if !self.tick_down() {
+                self.gravity_progress = Duration::ZERO;
+                break;
+            }
+            if self.soft_dropping {
+                self.stats.award_soft_drop(1);
+            }
+        }
+    }
+
+    fn advance_lock_delay(&mut self, dt: Duration) -> Result<bool, TopOut> {
+        let grounded = self.cursor_has_hit_bottom();
+        let lock = self.lock_delay.advance(dt, grounded, self.rules.lock_delay, self.rules.lock_mode);
+        if lock {
+            self.place_cursor()?;
+        }
+        Ok(lock)
+    }
+
+    /// Whether the cursor is resting on the stack or the floor.
     pub fn cursor_has_hit_bottom(&self) -> bool {
         self.cursor.is_some() &&
         self.ticked_down_cursor().is_none()
     }
 
     fn ticked_down_cursor(&self) -> Option<Piece> {
-        let Some(cursor) = self.cursor else { return None; };
-        let new = cursor.moved_by(Offset::new(0, -1));
-        (!self.matrix.is_clipping(&new)).then_some(new)
+        let new = self.cursor?.moved_by(Offset::new(0, -1));
+        (!self.matrix.is_clipping(&new, &*self.rotation_system)).then_some(new)
     }
 
-    pub fn hard_drop(&mut self) {
-        while let Some(new) = self.ticked_down_cursor() {
-            self.cursor = Some(new);
+    /// Drops the cursor as far as it can go and locks it straight away.
+    pub fn hard_drop(&mut self) -> Result<(), TopOut> {
+        if self.cursor.is_none() {
+            return Ok(());
         }
-        self.place_cursor();
+
+        let mut cells = 0;
+        while self.tick_down() {
+            cells += 1;
+        }
+        self.stats.award_hard_drop(cells);
+        self.place_cursor()
+    }
+
+    /// The stack, not including the cursor.
+    pub fn matrix(&self) -> &Matrix {
+        &self.matrix
     }
 
+    /// Every cell of the matrix along with its coordinate, bottom row first.
     pub fn cells(&self) -> CellIter<'_> {
         CellIter {
             position: Coordinate::origin(),
-            cells: self.matrix.0.iter(),
+            width: self.matrix.width,
+            cells: self.matrix.cells.iter(),
         }
     }
 
+    /// How long the cursor takes to fall a row at the current level, before soft dropping.
     pub fn drop_time(&self) -> Duration {
-        let level_index = self.level - 1;
-        let seconds_per_line = (0.8 - (level_index as f32 * 0.007)).powi(level_index as _);
-        Duration::from_secs_f32(seconds_per_line)
-    }
-
-    pub fn line_clear(
-        &mut self,
-        mut animation: impl FnMut(&[usize]),
-    ) {
-        let lines = self.matrix.full_lines();
-        animation(lines.as_slice());
-        self.matrix.clear_lines(lines.as_slice());
+        let level_index = ((self.stats.level - 1) as usize).min(DROP_TIMES.len() - 1);
+        Duration::from_nanos(DROP_TIMES[level_index])
     }
 }
 
-#[derive(Clone, Copy, PartialEq, Debug)]
-pub enum Color { Yellow, Cyan, Purple, Orange, Blue, Green, Red }
+/// What a cell looks like, by the kind of piece that left it.
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub enum Color {
+    /// Left by an O.
+    Yellow,
+    /// Left by an I.
+    Cyan,
+    /// Left by a T.
+    Purple,
+    /// Left by an L.
+    Orange,
+    /// Left by a J.
+    Blue,
+    /// Left by an S.
+    Green,
+    /// Left by a Z.
+    Red,
+}
 
-pub struct Matrix([Option<Color>;Self::SIZE]);
+/// The grid pieces lock into.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Matrix {
+    width: usize,
+    visible_height: usize,
+    cells: Vec<Option<Color>>,
+}
 
 impl Matrix {
-    pub const WIDTH: usize = 10;
-    pub const HEIGHT: usize = 20;
-    const SIZE: usize = Self::WIDTH * Self::HEIGHT;
+    /// Columns in a guideline matrix.
+    pub const DEFAULT_WIDTH: usize = 10;
+    /// Rows in view in a guideline matrix.
+    pub const DEFAULT_VISIBLE_HEIGHT: usize = 20;
+    /// Narrow enough and every piece would clip at spawn.
+    pub const MIN_WIDTH: usize = 4;
+
+    /// A matrix with `visible_height` rows in view, and the same again hidden above them for pieces to spawn into.
+    pub fn new(width: usize, visible_height: usize) -> Self {
+        assert!(width >= Self::MIN_WIDTH, "Matrix must be at least {} wide", Self::MIN_WIDTH);
+        assert!(visible_height > 0, "Matrix must have visible rows");
+        Self {
+            width,
+            visible_height,
+            cells: vec![None; width * visible_height * 2],
+        }
+    }
 
-    fn on_matrix(coord: Coordinate) -> bool {
-        Self::valid_coord(coord) && coord.y < Self::HEIGHT
+    /// An empty matrix of the default size.
+    pub fn blank() -> Self {
+        Self::new(Self::DEFAULT_WIDTH, Self::DEFAULT_VISIBLE_HEIGHT)
     }
 
-    fn valid_coord(coord: Coordinate) -> bool {
-        coord.x < Self::WIDTH
+    /// Columns across.
+    pub fn width(&self) -> usize {
+        self.width
     }
 
-    fn indexing(Coordinate { x, y }: Coordinate) -> usize {
-        y * Self::WIDTH + x
+    /// Rows including the hidden buffer above the visible ones.
+    pub fn height(&self) -> usize {
+        self.visible_height * 2
     }
 
-    pub fn blank() -> Self {
-        Self([None; Self::SIZE])
+    /// Rows in view, not including the hidden buffer.
+    pub fn visible_height(&self) -> usize {
+        self.visible_height
+    }
+
+    /// Whether `coord` is inside the matrix, buffer included.
+    pub fn on_matrix(&self, coord: Coordinate) -> bool {
+        self.valid_coord(coord) && coord.y < self.height()
+    }
+
+    fn valid_coord(&self, coord: Coordinate) -> bool {
+        coord.x < self.width
+    }
+
+    fn indexing(&self, Coordinate { x, y }: Coordinate) -> usize {
+        y * self.width + x
     }
 
-    fn is_clipping(&self, piece: &Piece) -> bool {
-        let Some(cells) = piece.cells() else { return true; };
+    /// Whether there's a cell at `coord`. Off the matrix there never is.
+    pub fn is_occupied(&self, coord: Coordinate) -> bool {
+        self.on_matrix(coord) && self[coord].is_some()
+    }
+
+    /// Whether `piece` overlaps the stack or the walls. Cells above the buffer don't count.
+    pub fn is_clipping(&self, piece: &Piece, rotation_system: &dyn RotationSystem) -> bool {
+        let Some(cells) = piece.cells(rotation_system) else { return true; };
         cells.into_iter().any(|coord|
-            !Matrix::valid_coord(coord) ||
-            (Matrix::on_matrix(coord) && self[coord].is_some())
+            !self.valid_coord(coord) ||
+            (self.on_matrix(coord) && self[coord].is_some())
         )
     }
 
-    fn is_placeable(&self, piece: &Piece) -> bool {
-        let Some(cells) = piece.cells() else { return false; };
+    /// Whether every cell of `piece` is on the matrix and empty.
+    pub fn is_placeable(&self, piece: &Piece, rotation_system: &dyn RotationSystem) -> bool {
+        let Some(cells) = piece.cells(rotation_system) else { return false; };
         cells.into_iter().all(|coord|
-            Matrix::on_matrix(coord) &&
+            self.on_matrix(coord) &&
             self[coord].is_none()
         )
     }
 
-    fn lines(&self) -> ArrayChunks<'_, Option<Color>, {Self::WIDTH}> {
-        self.0.array_chunks()
+    /// Each row, from the bottom up.
+    pub fn lines(&self) -> ChunksExact<'_, Option<Color>> {
+        self.cells.chunks_exact(self.width)
     }
 
-    fn full_lines(&self) -> Vec<usize> {
+    /// Indices of the rows with no gaps, from the bottom up.
+    pub fn full_lines(&self) -> Vec<usize> {
         self.lines()
             .enumerate()
             .filter(|(_, line)|
@@ -198,11 +775,12 @@ impl Matrix {
     }
 
     fn clear_lines(&mut self, indices: &[usize]) {
-        debug_assert!(indices.is_sorted());
+        debug_assert!(indices.windows(2).all(|pair| pair[0] <= pair[1]));
+        let size = self.cells.len();
         for index in indices.iter().rev() {
-            let start_of_remainder = Self::WIDTH * (index + 1);
-            self.0.copy_within(start_of_remainder.., (index * Self::WIDTH));
-            self.0[Self::SIZE - Self::WIDTH..].fill(None);
+            let start_of_remainder = self.width * (index + 1);
+            self.cells.copy_within(start_of_remainder.., index * self.width);
+            self.cells[size - self.width..].fill(None);
         }
     }
 }
@@ -211,20 +789,23 @@ impl Index<Coordinate> for Matrix {
     type Output = Option<Color>;
 
     fn index(&self, coord: Coordinate) -> &Self::Output {
-        assert!(Self::on_matrix(coord));
-        &self.0[Self::indexing(coord)]
+        assert!(self.on_matrix(coord));
+        &self.cells[self.indexing(coord)]
     }
 }
 
 impl IndexMut<Coordinate> for Matrix {
     fn index_mut(&mut self, coord: Coordinate) -> &mut Self::Output {
-        assert!(Self::on_matrix(coord));
-        &mut self.0[Self::indexing(coord)]
+        assert!(self.on_matrix(coord));
+        let index = self.indexing(coord);
+        &mut self.cells[index]
     }
 }
 
+/// Iterator returned by [`Engine::cells`].
 pub struct CellIter<'matrix> {
     position: Coordinate,
+    width: usize,
     cells: ::std::slice::Iter<'matrix, Option<Color>>,
 }
 
@@ -232,12 +813,10 @@ impl<'matrix> Iterator for CellIter<'matrix> {
     type Item = (Coordinate, Option<Color>) ;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let Some(&cell) = self.cells.next() else {
-            return None;
-        };
+        let &cell = self.cells.next()?;
 
         let coord = self.position;
-        self.position.grid_inc();
+        self.position.grid_inc(self.width);
 
         Some((coord, cell))
     }
@@ -255,7 +834,8 @@ mod test {
 
         let mut iter = CellIter {
             position: Coordinate::origin(),
-            cells: matrix.0.iter(),
+            width: matrix.width,
+            cells: matrix.cells.iter(),
         };
 
         let first_five = (&mut iter).take(5).collect::<Vec<_>>();
@@ -270,7 +850,7 @@ mod test {
             ],
         );
 
-        let other_item = (&mut iter).skip(8).next();
+        let other_item = iter.nth(8);
         assert_eq!(
             other_item,
             Some((Coordinate::new(3, 1), Some(Color::Green))),
@@ -278,4 +858,706 @@ mod test {
 
         assert!(iter.all(|(_, contents)| contents.is_none()));
     }
+
+    /// Builds a matrix from rows of `#` (filled) and `.` (empty), top row first.
+    pub(super) fn matrix_from(rows: &[&str]) -> Matrix {
+        let mut matrix = Matrix::blank();
+        for (row, line) in rows.iter().rev().enumerate() {
+            for (col, cell) in line.chars().enumerate() {
+                if cell == '#' {
+                    matrix[Coordinate::new(col, row)] = Some(Color::Red);
+                }
+            }
+        }
+        matrix
+    }
+
+    /// Rows and T-spin of the last line clear in the event queue.
+    fn cleared(engine: &mut Engine) -> (Vec<usize>, TSpin) {
+        engine.drain_events()
+            .filter_map(|event| match event {
+                EngineEvent::LinesCleared { rows, tspin, .. } => Some((rows, tspin)),
+                _ => None,
+            })
+            .last()
+            .unwrap_or_default()
+    }
+
+    fn engine_with_cursor(matrix: Matrix, kind: PieceKind, rotation: Rotation, position: Offset) -> Engine {
+        let mut engine = Engine::with_matrix(matrix);
+        engine.cursor = Some(Piece { kind, rotation, position });
+        engine
+    }
+
+    #[test]
+    fn every_kick_is_reachable() {
+        const ROTATIONS: [Rotation;4] = [Rotation::N, Rotation::E, Rotation::S, Rotation::W];
+        let kinds = [PieceKind::I, PieceKind::T, PieceKind::L, PieceKind::J, PieceKind::S, PieceKind::Z];
+        let start = Offset::new(4, 6);
+
+        for kind in kinds {
+            for from in ROTATIONS {
+                for direction in [RotateKind::Clockwise, RotateKind::CounterClockwise] {
+                    let to = direction.apply(from);
+                    let piece = Piece { kind, rotation: from, position: start };
+                    let kicks = Srs.kicks(&Matrix::blank(), &piece, to);
+                    for (index, kick) in kicks.into_iter().enumerate() {
+                        let expected = Piece { kind, rotation: to, position: start + kick };
+
+                        // Fill everything except where the expected kick lands,
+                        // so every earlier test has to fail.
+                        let mut matrix = Matrix::blank();
+                        matrix.cells.fill(Some(Color::Red));
+                        for coord in expected.cells(&Srs).unwrap() {
+                            matrix[coord] = None;
+                        }
+
+                        let mut engine = engine_with_cursor(matrix, kind, from, start);
+                        assert_eq!(
+                            engine.rotate_cursor(direction),
+                            Ok(index),
+                            "{:?} {:?} -> {:?}",
+                            kind,
+                            from,
+                            to,
+                        );
+                        assert_eq!(engine.cursor, Some(expected));
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn blocked_rotation_leaves_cursor() {
+        let mut matrix = Matrix::blank();
+        matrix.cells.fill(Some(Color::Red));
+
+        let start = Piece { kind: PieceKind::T, rotation: Rotation::N, position: Offset::new(4, 6) };
+        let mut engine = engine_with_cursor(matrix, start.kind, start.rotation, start.position);
+
+        assert_eq!(engine.rotate_cursor(RotateKind::Clockwise), Err(MoveError::Blocked));
+        assert_eq!(engine.rotate_cursor(RotateKind::CounterClockwise), Err(MoveError::Blocked));
+        assert_eq!(engine.cursor, Some(start));
+    }
+
+    #[test]
+    fn half_turn_flips_the_cursor() {
+        let mut engine = engine_with_cursor(Matrix::blank(), PieceKind::T, Rotation::N, Offset::new(3, 3));
+        assert_eq!(engine.rotate_cursor(RotateKind::Half), Ok(0));
+        assert_eq!(engine.cursor.unwrap().rotation, Rotation::S);
+
+        let matrix = matrix_from(&["....#....."]);
+        let mut engine = engine_with_cursor(matrix, PieceKind::T, Rotation::N, Offset::new(3, 0));
+        assert_eq!(engine.rotate_cursor(RotateKind::Half), Err(MoveError::Blocked));
+    }
+
+    #[test]
+    fn o_rotates_in_place() {
+        let mut engine = engine_with_cursor(Matrix::blank(), PieceKind::O, Rotation::N, Offset::new(3, 3));
+        let before = engine.cursor.unwrap().cells(&Srs);
+
+        assert_eq!(engine.rotate_cursor(RotateKind::Clockwise), Ok(0));
+        assert_eq!(engine.cursor.unwrap().rotation, Rotation::E);
+        assert_eq!(engine.cursor.unwrap().cells(&Srs), before);
+    }
+
+    #[test]
+    fn i_kicks_off_left_wall() {
+        let mut engine = engine_with_cursor(Matrix::blank(), PieceKind::I, Rotation::W, Offset::new(-1, 5));
+
+        assert_eq!(engine.rotate_cursor(RotateKind::Clockwise), Ok(1));
+        assert_eq!(
+            engine.cursor.unwrap().cells(&Srs),
+            Some([(0, 7), (1, 7), (2, 7), (3, 7)].map(Coordinate::from)),
+        );
+    }
+
+    #[test]
+    fn spawns_centred_above_the_stack() {
+        for kind in PieceKind::ALL {
+            let mut engine = Engine::new();
+            engine.spawn(kind).unwrap();
+
+            let cells = engine.cursor.unwrap().cells(&Srs).unwrap();
+            let columns = cells.map(|coord| coord.x);
+            let expected_columns = match kind {
+                PieceKind::O => 4..=5,
+                PieceKind::I => 3..=6,
+                _ => 3..=5,
+            };
+            assert!(columns.iter().all(|x| expected_columns.contains(x)), "{:?}: {:?}", kind, cells);
+
+            let lowest = cells.iter().map(|coord| coord.y).min().unwrap();
+            assert_eq!(lowest, Matrix::DEFAULT_VISIBLE_HEIGHT - 1, "{:?}", kind);
+        }
+    }
+
+    #[test]
+    fn bag_deals_every_kind() {
+        let mut engine = Engine::new();
+        let mut dealt = Vec::new();
+        for _ in 0..PieceKind::ALL.len() {
+            engine.spawn_next().unwrap();
+            dealt.push(engine.cursor.take().unwrap().kind);
+        }
+
+        for kind in PieceKind::ALL {
+            assert!(dealt.contains(&kind), "{:?} missing from {:?}", kind, dealt);
+        }
+    }
+
+    #[test]
+    fn seeded_engines_agree() {
+        let mut first = Engine::with_randomizer(Bag::seven(42));
+        let mut second = Engine::with_randomizer(Bag::seven(42));
+        for _ in 0..20 {
+            first.spawn_next().unwrap();
+            second.spawn_next().unwrap();
+            assert_eq!(first.cursor.take(), second.cursor.take());
+        }
+    }
+
+    #[test]
+    fn hold_swaps_once_per_piece() {
+        let mut engine = Engine::with_randomizer(Bag::seven(3));
+        let mut reference = Bag::seven(3);
+        engine.spawn(PieceKind::T).unwrap();
+        engine.move_cursor(MoveKind::Left).unwrap();
+
+        engine.hold().unwrap();
+        assert_eq!(engine.held_piece(), Some(PieceKind::T));
+        assert!(engine.hold_used());
+        let pulled = engine.cursor.unwrap();
+        assert_eq!(pulled.kind, reference.next());
+
+        // A second hold before locking does nothing
+        engine.hold().unwrap();
+        assert_eq!(engine.held_piece(), Some(PieceKind::T));
+        assert_eq!(engine.cursor, Some(pulled));
+
+        engine.hard_drop().unwrap();
+        assert!(!engine.hold_used());
+
+        engine.spawn(PieceKind::I).unwrap();
+        engine.hold().unwrap();
+        assert_eq!(engine.held_piece(), Some(PieceKind::I));
+
+        // The held piece comes back at the spawn position and rotation
+        let mut fresh = Engine::new();
+        fresh.spawn(PieceKind::T).unwrap();
+        assert_eq!(engine.cursor, fresh.cursor);
+    }
+
+    #[test]
+    fn preview_matches_spawns() {
+        let mut engine = Engine::with_randomizer(Bag::seven(7));
+        let mut reference = Bag::seven(7);
+
+        let preview = engine.preview(3).collect::<Vec<_>>();
+        assert_eq!(preview.len(), 3);
+
+        // Asking for more than a bag's worth deals into the next bag
+        let long_preview = engine.preview(16).collect::<Vec<_>>();
+        assert_eq!(long_preview[..3], preview);
+
+        for expected in long_preview {
+            assert_eq!(reference.next(), expected);
+            engine.spawn_next().unwrap();
+            assert_eq!(engine.cursor.take().unwrap().kind, expected);
+        }
+    }
+
+    #[test]
+    fn drop_times_follow_the_guideline_curve() {
+        for (index, &nanos) in DROP_TIMES.iter().enumerate() {
+            let seconds = (0.8 - index as f64 * 0.007).powi(index as i32);
+            assert!((seconds * 1e9 - nanos as f64).abs() < 1.0, "level {}", index + 1);
+        }
+    }
+
+    #[test]
+    fn gravity_drops_a_row_per_drop_time() {
+        let mut engine = Engine::new();
+        engine.spawn(PieceKind::T).unwrap();
+        let start = engine.cursor.unwrap().position.y;
+
+        let half = engine.drop_time() / 2;
+        engine.update(half).unwrap();
+        assert_eq!(engine.cursor.unwrap().position.y, start);
+        engine.update(half).unwrap();
+        assert_eq!(engine.cursor.unwrap().position.y, start - 1);
+
+        engine.update(engine.drop_time() * 3).unwrap();
+        assert_eq!(engine.cursor.unwrap().position.y, start - 4);
+    }
+
+    #[test]
+    fn gravity_caps_at_20g() {
+        let mut engine = Engine::new();
+        engine.stats.level = 30;
+        engine.spawn(PieceKind::O).unwrap();
+
+        engine.update(MIN_DROP_TIME * 18).unwrap();
+        assert!(!engine.cursor_has_hit_bottom());
+        engine.update(MIN_DROP_TIME).unwrap();
+        assert!(engine.cursor_has_hit_bottom());
+    }
+
+    #[test]
+    fn soft_drop_multiplies_gravity() {
+        let mut engine = Engine::new();
+        engine.spawn(PieceKind::T).unwrap();
+        let start = engine.cursor.unwrap().position.y;
+
+        engine.set_soft_drop(true);
+        engine.update(engine.drop_time() / 20 * 3).unwrap();
+        assert_eq!(engine.cursor.unwrap().position.y, start - 3);
+        assert_eq!(engine.stats().score, 3);
+
+        engine.set_soft_drop(false);
+        engine.update(engine.drop_time() / 20 * 3).unwrap();
+        assert_eq!(engine.cursor.unwrap().position.y, start - 3);
+        assert_eq!(engine.stats().score, 3);
+    }
+
+    #[test]
+    fn sonic_drop_doesnt_lock() {
+        let mut engine = Engine::with_rules(Rules { soft_drop: SoftDrop::Sonic, ..Rules::default() });
+        engine.spawn(PieceKind::T).unwrap();
+        let start = engine.cursor.unwrap().position.y;
+
+        engine.set_soft_drop(true);
+        assert_eq!(engine.update(Duration::ZERO), Ok(()));
+        assert_eq!(engine.phase(), Phase::Locking);
+        assert!(engine.cursor_has_hit_bottom());
+        assert_eq!(engine.stats().score, start as u64 + 1);
+    }
+
+    #[test]
+    fn line_clear_scores_the_lock() {
+        let mut engine = Engine::with_matrix(matrix_from(&[
+            "#.########",
+            "#.########",
+        ]));
+        engine.spawn(PieceKind::I).unwrap();
+        engine.rotate_cursor(RotateKind::CounterClockwise).unwrap();
+        while engine.cursor.unwrap().position.x > 0 {
+            engine.move_cursor(MoveKind::Left).unwrap();
+        }
+        engine.hard_drop().unwrap();
+
+        // Seventeen rows of hard drop, then a double
+        assert_eq!(engine.stats().score, 2 * 17 + 300);
+        assert_eq!(engine.stats().lines, 2);
+    }
+
+    #[test]
+    fn phases_after_a_line_clear() {
+        let rules = Rules {
+            line_clear_delay: FRAME * 3,
+            entry_delay: FRAME * 2,
+            ..Rules::default()
+        };
+        let mut engine = Engine { matrix: matrix_from(&["####..####"]), ..Engine::with_rules(rules) };
+        engine.spawn(PieceKind::O).unwrap();
+        assert_eq!(engine.phase(), Phase::Falling);
+
+        engine.hard_drop().unwrap();
+        let rows = vec![0];
+        assert_eq!(engine.phase(), Phase::LineClearDelay { rows: rows.clone(), elapsed: Duration::ZERO, chain: 0 });
+
+        engine.update(FRAME * 2).unwrap();
+        assert_eq!(engine.phase(), Phase::LineClearDelay { rows, elapsed: FRAME * 2, chain: 0 });
+        assert!(engine.matrix[Coordinate::new(0, 0)].is_some());
+
+        // Time left over from one delay carries into the next
+        engine.update(FRAME * 2).unwrap();
+        assert_eq!(engine.phase(), Phase::EntryDelay { elapsed: FRAME });
+        assert_eq!(engine.matrix[Coordinate::new(0, 0)], None);
+        assert_eq!(engine.matrix[Coordinate::new(4, 0)], Some(Color::Yellow));
+        assert!(engine.cursor.is_none());
+
+        engine.update(FRAME).unwrap();
+        assert_eq!(engine.phase(), Phase::Falling);
+        assert!(engine.cursor.is_some());
+    }
+
+    #[test]
+    fn zero_delays_spawn_straight_away() {
+        let rules = Rules {
+            line_clear_delay: Duration::ZERO,
+            entry_delay: Duration::ZERO,
+            ..Rules::default()
+        };
+        let mut engine = Engine { matrix: matrix_from(&["####..####"]), ..Engine::with_rules(rules) };
+        engine.spawn(PieceKind::O).unwrap();
+
+        engine.hard_drop().unwrap();
+        assert_eq!(engine.phase(), Phase::Falling);
+        assert_eq!(engine.matrix[Coordinate::new(0, 0)], None);
+    }
+
+    #[test]
+    fn sticky_gravity_chains() {
+        let rules = Rules {
+            line_clear_delay: FRAME,
+            line_clear_gravity: LineClearGravity::Sticky,
+            ..Rules::default()
+        };
+        let matrix = matrix_from(&[
+            ".....#....",
+            "######....",
+            "#####.####",
+            "#########.",
+        ]);
+        let mut engine = Engine { matrix, ..Engine::with_rules(rules) };
+        engine.cursor = Some(Piece { kind: PieceKind::I, rotation: Rotation::N, position: Offset::new(6, 5) });
+        engine.hard_drop().unwrap();
+        assert_eq!(cleared(&mut engine), (vec![2], TSpin::None));
+
+        // With the row under it gone, the loose cell falls into the gap below
+        engine.update(FRAME).unwrap();
+        assert_eq!(engine.phase(), Phase::LineClearDelay { rows: vec![1], elapsed: Duration::ZERO, chain: 1 });
+        assert!(matches!(
+            engine.drain_events().next(),
+            Some(EngineEvent::LinesCleared { chain: 1, combo: Some(1), .. }),
+        ));
+
+        engine.update(FRAME).unwrap();
+        assert!(matches!(engine.phase(), Phase::EntryDelay { .. }));
+        assert_eq!(engine.stats().lines, 2);
+    }
+
+    #[test]
+    fn consecutive_clears_build_a_combo() {
+        let mut engine = Engine::with_matrix(matrix_from(&["####..####"; 4]));
+        engine.spawn(PieceKind::O).unwrap();
+        engine.hard_drop().unwrap();
+        assert_eq!(engine.stats().combo, Some(0));
+
+        let rules = Rules::default();
+        engine.update(rules.line_clear_delay + rules.entry_delay).unwrap();
+        assert_eq!(engine.stats().combo, Some(0));
+        assert!(!engine.drain_events().any(|event| matches!(event, EngineEvent::LinesCleared { chain: 1, .. })));
+
+        engine.cursor = None;
+        engine.spawn(PieceKind::O).unwrap();
+        engine.hard_drop().unwrap();
+        assert_eq!(engine.stats().combo, Some(1));
+        assert_eq!(engine.stats().lines, 4);
+    }
+
+    #[test]
+    fn engine_starts_in_entry_delay() {
+        let mut engine = Engine::new();
+        assert!(matches!(engine.phase(), Phase::EntryDelay { .. }));
+        engine.update(Rules::default().entry_delay).unwrap();
+        assert!(engine.cursor.is_some());
+    }
+
+    #[test]
+    fn events_are_queued_in_order() {
+        let mut engine = Engine::with_matrix(matrix_from(&["####..####"]));
+        engine.spawn(PieceKind::O).unwrap();
+        engine.move_cursor(MoveKind::Left).unwrap();
+        engine.move_cursor(MoveKind::Right).unwrap();
+        engine.rotate_cursor(RotateKind::Clockwise).unwrap();
+        engine.hard_drop().unwrap();
+
+        assert_eq!(
+            engine.drain_events().collect::<Vec<_>>(),
+            [
+                EngineEvent::PieceSpawned(PieceKind::O),
+                EngineEvent::Moved(MoveKind::Left),
+                EngineEvent::Moved(MoveKind::Right),
+                EngineEvent::Rotated { kick: 0 },
+                EngineEvent::Locked(PieceKind::O),
+                EngineEvent::LinesCleared {
+                    rows: vec![0],
+                    tspin: TSpin::None,
+                    combo: Some(0),
+                    back_to_back: false,
+                    chain: 0,
+                },
+            ],
+        );
+        assert_eq!(engine.drain_events().count(), 0);
+    }
+
+    #[test]
+    fn top_out_is_an_event() {
+        let mut rows = vec!["###....###"; Matrix::DEFAULT_VISIBLE_HEIGHT - 1];
+        rows.insert(0, "##########");
+        let mut engine = Engine::with_matrix(matrix_from(&rows));
+        engine.spawn(PieceKind::T).unwrap();
+        engine.hold().unwrap();
+
+        let _ = engine.hard_drop();
+        let events = engine.drain_events().collect::<Vec<_>>();
+        assert!(events.contains(&EngineEvent::Hold(PieceKind::T)));
+        assert_eq!(events.last(), Some(&EngineEvent::TopOut(TopOut::LockOut)));
+    }
+
+    fn grounded_engine(lock_mode: LockMode) -> Engine {
+        let mut engine = Engine::with_rules(Rules { lock_mode, ..Rules::default() });
+        engine.spawn(PieceKind::T).unwrap();
+        while engine.tick_down() {}
+        engine
+    }
+
+    const FRAME: Duration = Duration::from_millis(100);
+
+    #[test]
+    fn locks_after_delay() {
+        let mut engine = Engine::new();
+        engine.spawn(PieceKind::T).unwrap();
+
+        // Not on the ground, so nothing happens
+        assert_eq!(engine.advance_lock_delay(Duration::from_secs(10)), Ok(false));
+
+        while engine.tick_down() {}
+        for _ in 0..4 {
+            assert_eq!(engine.advance_lock_delay(FRAME), Ok(false));
+        }
+        assert_eq!(engine.advance_lock_delay(FRAME), Ok(true));
+        assert!(engine.cursor.is_none());
+    }
+
+    #[test]
+    fn extended_placement_runs_out_of_resets() {
+        let mut engine = grounded_engine(LockMode::ExtendedPlacement);
+        let mut direction = MoveKind::Left;
+
+        for _ in 0..LockDelay::MAX_RESETS {
+            assert_eq!(engine.advance_lock_delay(FRAME * 4), Ok(false));
+            engine.move_cursor(direction).unwrap();
+            direction = match direction {
+                MoveKind::Left => MoveKind::Right,
+                MoveKind::Right => MoveKind::Left,
+            };
+        }
+
+        assert_eq!(engine.advance_lock_delay(Duration::ZERO), Ok(true));
+    }
+
+    #[test]
+    fn extended_placement_refunds_on_new_row() {
+        let mut engine = Engine::with_matrix(matrix_from(&[".#########"]));
+        engine.spawn(PieceKind::I).unwrap();
+        engine.rotate_cursor(RotateKind::Clockwise).unwrap();
+        while engine.tick_down() {}
+
+        for _ in 0..LockDelay::MAX_RESETS / 2 {
+            engine.advance_lock_delay(FRAME).unwrap();
+            engine.move_cursor(MoveKind::Right).unwrap();
+            engine.move_cursor(MoveKind::Left).unwrap();
+        }
+
+        // Using up the last reset to slide over the gap, then falling into it
+        while engine.move_cursor(MoveKind::Left).is_ok() {}
+        assert!(engine.tick_down());
+        assert_eq!(engine.advance_lock_delay(FRAME), Ok(false));
+    }
+
+    #[test]
+    fn infinite_never_runs_out() {
+        let mut engine = grounded_engine(LockMode::Infinite);
+        for _ in 0..100 {
+            assert_eq!(engine.advance_lock_delay(FRAME * 4), Ok(false));
+            engine.move_cursor(MoveKind::Left).unwrap();
+            engine.move_cursor(MoveKind::Right).unwrap();
+        }
+        assert_eq!(engine.advance_lock_delay(FRAME * 5), Ok(true));
+    }
+
+    #[test]
+    fn step_reset_ignores_moves() {
+        let mut engine = grounded_engine(LockMode::StepReset);
+        assert_eq!(engine.advance_lock_delay(FRAME * 4), Ok(false));
+        engine.move_cursor(MoveKind::Left).unwrap();
+        assert_eq!(engine.advance_lock_delay(FRAME), Ok(true));
+    }
+
+    #[test]
+    fn locking_above_the_matrix_is_lock_out() {
+        let mut rows = vec!["###....###"; Matrix::DEFAULT_VISIBLE_HEIGHT - 1];
+        rows.insert(0, "##########");
+        let mut engine = Engine::with_matrix(matrix_from(&rows));
+
+        engine.spawn(PieceKind::T).unwrap();
+        assert_eq!(engine.hard_drop(), Err(TopOut::LockOut));
+    }
+
+    #[test]
+    fn locking_partly_in_the_buffer_is_fine() {
+        let mut rows = vec!["###....###"; Matrix::DEFAULT_VISIBLE_HEIGHT - 1];
+        rows.insert(0, "####.#####");
+        let matrix = matrix_from(&rows);
+        let top = Matrix::DEFAULT_VISIBLE_HEIGHT as isize - 1;
+        let mut engine = engine_with_cursor(matrix, PieceKind::T, Rotation::S, Offset::new(3, top));
+
+        assert_eq!(engine.hard_drop(), Ok(()));
+        assert!(engine.matrix[Coordinate::new(4, Matrix::DEFAULT_VISIBLE_HEIGHT - 1)].is_some());
+        assert!(engine.matrix[Coordinate::new(4, Matrix::DEFAULT_VISIBLE_HEIGHT)].is_some());
+    }
+
+    #[test]
+    fn buffer_rows_block_pieces() {
+        let mut engine = Engine::with_matrix(matrix_from(&["#........."]));
+        let buffer_row = Coordinate::new(4, Matrix::DEFAULT_VISIBLE_HEIGHT + 1);
+        engine.matrix[buffer_row] = Some(Color::Red);
+
+        assert_eq!(engine.spawn(PieceKind::T), Err(TopOut::BlockOut));
+    }
+
+    #[test]
+    fn narrow_matrix() {
+        let mut engine = Engine::with_matrix(Matrix::new(4, 20));
+        engine.spawn(PieceKind::I).unwrap();
+        engine.hard_drop().unwrap();
+
+        engine.update(Rules::default().line_clear_delay).unwrap();
+        assert!(engine.cells().all(|(_, cell)| cell.is_none()));
+        assert_eq!(engine.cells().count(), 4 * 40);
+    }
+
+    #[test]
+    fn wide_matrix() {
+        let mut engine = Engine::with_matrix(Matrix::new(12, 20));
+        engine.spawn(PieceKind::O).unwrap();
+        let columns = engine.cursor_info().unwrap().0.map(|coord| coord.x);
+        assert!(columns.iter().all(|x| (5..=6).contains(x)), "{:?}", columns);
+
+        for _ in 0..5 {
+            engine.move_cursor(MoveKind::Right).unwrap();
+        }
+        assert_eq!(engine.move_cursor(MoveKind::Right), Err(MoveError::Blocked));
+        engine.hard_drop().unwrap();
+        assert_eq!(engine.matrix[Coordinate::new(11, 0)], Some(Color::Yellow));
+    }
+
+    #[test]
+    fn tall_matrix_spawns_above_its_visible_rows() {
+        let mut engine = Engine::with_matrix(Matrix::new(10, 40));
+        engine.spawn(PieceKind::T).unwrap();
+        let lowest = engine.cursor_info().unwrap().0.iter().map(|coord| coord.y).min();
+        assert_eq!(lowest, Some(39));
+    }
+
+    #[test]
+    fn hard_drop_lands_on_stack() {
+        let mut engine = Engine::with_matrix(matrix_from(&["##########"]));
+        engine.spawn(PieceKind::O).unwrap();
+
+        assert_eq!(engine.hard_drop(), Ok(()));
+        assert!(engine.cursor.is_none());
+        for coord in [(4, 1), (5, 1), (4, 2), (5, 2)] {
+            assert_eq!(engine.matrix[Coordinate::from(coord)], Some(Color::Yellow));
+        }
+    }
+
+    #[test]
+    fn ghost_lands_where_hard_drop_does() {
+        let mut engine = Engine::with_matrix(matrix_from(&["##....####", "###..#####"]));
+        engine.spawn(PieceKind::S).unwrap();
+        engine.move_cursor(MoveKind::Left).unwrap();
+
+        let (ghost, color) = engine.ghost_info().unwrap();
+        assert_eq!(color, Color::Green);
+        assert!(engine.cursor_info().unwrap().0 != ghost);
+
+        engine.hard_drop().unwrap();
+        for coord in ghost {
+            assert_eq!(engine.matrix[coord], Some(Color::Green));
+        }
+        assert_eq!(engine.ghost_info(), None);
+    }
+
+    #[test]
+    fn t_spin_triple_kick() {
+        let matrix = matrix_from(&[
+            "....#.....",
+            "....######",
+            "####.#####",
+            "####..####",
+            "####.#####",
+            "#########.",
+            "#########.",
+        ]);
+        let mut engine = engine_with_cursor(matrix, PieceKind::T, Rotation::N, Offset::new(4, 4));
+
+        assert_eq!(engine.rotate_cursor(RotateKind::Clockwise), Ok(4));
+        assert_eq!(
+            engine.cursor.unwrap().cells(&Srs),
+            Some([(4, 4), (4, 3), (4, 2), (5, 3)].map(Coordinate::from)),
+        );
+
+        engine.hard_drop().unwrap();
+        assert_eq!(cleared(&mut engine), (vec![2, 3, 4], TSpin::Full));
+    }
+
+    #[test]
+    fn last_kick_makes_a_full_t_spin() {
+        // Only one of the corners the T points to is filled, so it'd otherwise be a mini
+        let matrix = matrix_from(&[
+            "....#.....",
+            "....######",
+            "####..####",
+            "####..####",
+            "####.#####",
+        ]);
+        let mut engine = engine_with_cursor(matrix, PieceKind::T, Rotation::N, Offset::new(4, 2));
+
+        assert_eq!(engine.rotate_cursor(RotateKind::Clockwise), Ok(4));
+        engine.hard_drop().unwrap();
+        assert_eq!(cleared(&mut engine), (vec![0, 1], TSpin::Full));
+    }
+
+    #[test]
+    fn t_spin_double() {
+        let matrix = matrix_from(&[
+            "##........",
+            "#...######",
+            "##.#######",
+        ]);
+        let mut engine = engine_with_cursor(matrix, PieceKind::T, Rotation::E, Offset::new(1, 0));
+        engine.rotate_cursor(RotateKind::Clockwise).unwrap();
+
+        engine.hard_drop().unwrap();
+        assert_eq!(cleared(&mut engine), (vec![0, 1], TSpin::Full));
+    }
+
+    #[test]
+    fn t_spin_mini_on_the_floor() {
+        let matrix = matrix_from(&[
+            "...#......",
+            "..........",
+            "...#.#....",
+        ]);
+        let mut engine = engine_with_cursor(matrix, PieceKind::T, Rotation::N, Offset::new(3, 0));
+        assert_eq!(engine.rotate_cursor(RotateKind::Clockwise), Ok(0));
+
+        engine.hard_drop().unwrap();
+        assert_eq!(cleared(&mut engine), (vec![], TSpin::Mini));
+    }
+
+    #[test]
+    fn moving_after_rotating_isnt_a_t_spin() {
+        let mut engine = engine_with_cursor(Matrix::blank(), PieceKind::T, Rotation::N, Offset::new(4, 6));
+        engine.rotate_cursor(RotateKind::Clockwise).unwrap();
+        assert_eq!(engine.last_rotation, Some(0));
+        engine.move_cursor(MoveKind::Left).unwrap();
+        assert_eq!(engine.last_rotation, None);
+
+        // Sliding into a T-spin slot doesn't count either
+        let matrix = matrix_from(&[
+            "##........",
+            "#...######",
+            "##.#######",
+        ]);
+        let mut engine = engine_with_cursor(matrix, PieceKind::T, Rotation::S, Offset::new(1, 0));
+        engine.hard_drop().unwrap();
+        assert_eq!(cleared(&mut engine), (vec![0, 1], TSpin::None));
+    }
 }
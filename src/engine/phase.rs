@@ -0,0 +1,30 @@
+use std::time::Duration;
+use serde::{Serialize, Deserialize};
+use super::TopOut;
+
+/// Where the engine is in the cycle from one piece to the next:
+/// falling, locking, waiting out the line clear and entry delays, then spawning again.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub enum Phase {
+    /// The cursor is in the air.
+    Falling,
+    /// The cursor is resting on the stack, and will lock when the lock delay runs out.
+    Locking,
+    /// These full rows are about to be cleared, which has been pending for `elapsed`.
+    /// `chain` counts the clears since the piece locked, for rows filled by stack falling after a clear.
+    LineClearDelay {
+        /// The full rows, from the bottom up.
+        rows: Vec<usize>,
+        /// How long the clear has been waiting.
+        elapsed: Duration,
+        /// Clears from the same piece before this one.
+        chain: u32,
+    },
+    /// Waiting to spawn the next piece.
+    EntryDelay {
+        /// How long it's been waiting.
+        elapsed: Duration,
+    },
+    /// The game is over.
+    ToppedOut(TopOut),
+}
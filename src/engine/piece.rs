@@ -1,16 +1,23 @@
 use cgmath::{EuclideanSpace, Zero};
-use super::{Coordinate, Offset, Matrix, Color};
+use serde::{Serialize, Deserialize};
+use super::{Coordinate, Offset, Color, rotation_system::RotationSystem};
 
-#[derive(Clone, Copy, PartialEq, Debug)]
-pub(super) struct Piece {
+/// A tetromino somewhere on (or off) the matrix.
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Piece {
+    /// Which of the seven shapes it is.
     pub kind: Kind,
+    /// The bottom left of its bounding box.
     pub position: Offset,
+    /// Which way it's facing.
     pub rotation: Rotation,
 }
 
 impl Piece {
+    /// Every piece is four cells.
     pub const CELL_COUNT: usize = 4;
 
+    /// The same piece, shifted by `offset`.
     pub fn moved_by(&self, offset: Offset) -> Self {
         Self {
             position: self.position + offset,
@@ -18,68 +25,72 @@ impl Piece {
         }
     }
 
-    pub fn cells(&self) -> Option<[Coordinate;Self::CELL_COUNT]> {
-        let offsets = self.kind.cells()
-            .map(self.rotator())
+    /// Where each of the piece's cells is, as shaped by `rotation_system`.
+    /// `None` if any are off the bottom or left of the matrix.
+    pub fn cells(&self, rotation_system: &dyn RotationSystem) -> Option<[Coordinate;Self::CELL_COUNT]> {
+        let offsets = rotation_system
+            .shape(self.kind, self.rotation)
             .map(self.positioner());
 
         let mut coords = [Coordinate::origin();Self::CELL_COUNT];
 
         for (offset, coord_slot) in offsets.into_iter().zip(&mut coords) {
             let positive_offset = offset.cast::<usize>()?;
-            let coord = Coordinate::from_vec(positive_offset);
-
-            if Matrix::valid_coord(coord) {
-                *coord_slot = coord;
-            } else {
-                return None;
-            }
+            *coord_slot = Coordinate::from_vec(positive_offset);
         }
 
         Some(coords)
     }
 
-    fn rotator(&self) -> impl Fn(Offset) -> Offset + '_ {
-        |cell| match self.kind {
-            Kind::O => cell,
-            _ => {
-                let grid_offset = self.rotation.intrinsic_offset() * (self.kind.grid_size() - 1);
-                cell * self.rotation + grid_offset
-            }
-        }
-    }
-
     fn positioner(&self) -> impl Fn(Offset) -> Offset {
         let position = self.position;
         move |cell| cell + position
     }
 }
 
-#[derive(Clone, Copy, Debug, PartialEq)]
-pub enum Kind { O, I, T, L, J, S, Z }
+/// The seven tetrominoes, named after the letters they look like.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Kind {
+    /// The square.
+    O,
+    /// Four in a line.
+    I,
+    /// Three in a line with one in the middle on top.
+    T,
+    /// Three in a line with one on top at the right.
+    L,
+    /// Three in a line with one on top at the left.
+    J,
+    /// Two offset pairs, stepping up to the right.
+    S,
+    /// Two offset pairs, stepping up to the left.
+    Z,
+}
 
 impl Kind {
+    /// Every kind, in a fixed order.
     pub const ALL: [Self;7] = [Self::O, Self::I, Self::T, Self::L, Self::J, Self::S, Self::Z];
 
-    fn cells(&self) -> [Offset;Piece::CELL_COUNT] {
+    pub(super) fn cells(&self) -> [Offset;Piece::CELL_COUNT] {
         match self {
             Self::O => &[( 1,1), ( 1,2), (2,1), (2,2)],
             Self::I => &[( 0,2), ( 1,2), (2,2), (3,2)],
             Self::T => &[( 0,1), ( 1,1), (2,1), (1,2)],
             Self::L => &[( 0,1), ( 1,1), (2,1), (2,2)],
             Self::J => &[( 0,2), ( 0,1), (1,1), (2,1)],
-            Self::S => &[( 1,1), ( 1,1), (1,2), (2,2)],
+            Self::S => &[( 0,1), ( 1,1), (1,2), (2,2)],
             Self::Z => &[( 0,2), ( 1,2), (1,1), (2,1)],
         }.map(Offset::from)
     }
 
-    fn grid_size(&self) -> isize {
+    pub(super) fn grid_size(&self) -> isize {
         match self {
             Self::I => 4,
             _ => 3,
         }
     }
 
+    /// The guideline colour for this kind.
     pub fn color(&self) -> Color {
         match self {
             Self::O => Color::Yellow,
@@ -93,11 +104,41 @@ impl Kind {
     }
 }
 
-#[derive(Clone, Copy, PartialEq, Debug)]
-pub enum Rotation { N, E, S, W }
+/// Which way a piece is facing, by compass point. Pieces spawn facing north in SRS.
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub enum Rotation {
+    /// As spawned in SRS.
+    N,
+    /// A quarter turn clockwise from north.
+    E,
+    /// Upside down.
+    S,
+    /// A quarter turn counter clockwise from north.
+    W,
+}
 
 impl Rotation {
-    fn intrinsic_offset(&self) -> Offset {
+    /// A quarter turn clockwise from this one.
+    pub fn clockwise(self) -> Self {
+        match self {
+            Self::N => Self::E,
+            Self::E => Self::S,
+            Self::S => Self::W,
+            Self::W => Self::N,
+        }
+    }
+
+    /// A quarter turn counter clockwise from this one.
+    pub fn counter_clockwise(self) -> Self {
+        match self {
+            Self::N => Self::W,
+            Self::E => Self::N,
+            Self::S => Self::E,
+            Self::W => Self::S,
+        }
+    }
+
+    pub(super) fn intrinsic_offset(&self) -> Offset {
         match self {
             Self::N => Offset::zero(),
             Self::E => Offset::new(0, 1),
@@ -123,6 +164,7 @@ impl std::ops::Mul<Rotation> for Offset {
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::engine::rotation_system::Srs;
 
     #[test]
     fn s_piece_positioning() {
@@ -133,8 +175,31 @@ mod test {
         };
 
         assert_eq!(
-            z.cells(),
+            z.cells(&Srs),
             Some([(5, 6), (5, 7), (6, 7), (6, 8)].map(Coordinate::from))
         );
     }
+
+    #[test]
+    fn every_kind_has_distinct_cells() {
+        for kind in Kind::ALL {
+            let cells = kind.cells();
+            for (i, cell) in cells.iter().enumerate() {
+                assert!(
+                    !cells[i + 1..].contains(cell),
+                    "{:?} has a duplicate cell {:?}",
+                    kind,
+                    cell,
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn rotation_round_trip() {
+        for rotation in [Rotation::N, Rotation::E, Rotation::S, Rotation::W] {
+            assert_eq!(rotation.clockwise().counter_clockwise(), rotation);
+            assert_eq!(rotation.clockwise().clockwise().clockwise().clockwise(), rotation);
+        }
+    }
 }
@@ -0,0 +1,450 @@
+use std::{collections::VecDeque, fmt::Debug};
+use rand::{Rng, SeedableRng, seq::SliceRandom};
+use rand_chacha::ChaCha8Rng;
+use serde::{Serialize, Deserialize};
+use super::piece::Kind;
+
+/// Deals out the sequence of pieces. Every implementation is driven by an
+/// explicit seed, so the same seed always gives the same game.
+pub trait Randomizer: Debug + Send + Sync + BoxClone {
+    /// Deals the next piece.
+    fn next(&mut self) -> Kind;
+
+    /// Where this randomizer is up to, for saving. Only the built in ones can be saved.
+    fn state(&self) -> Option<RandomizerState> {
+        None
+    }
+}
+
+/// Lets boxed randomizers be cloned, along with the engine holding them.
+/// Implemented for every randomizer that's [`Clone`].
+pub trait BoxClone {
+    /// A boxed copy of this randomizer, at the same point in its sequence.
+    fn box_clone(&self) -> Box<dyn Randomizer>;
+}
+
+impl<T: Randomizer + Clone + 'static> BoxClone for T {
+    fn box_clone(&self) -> Box<dyn Randomizer> {
+        Box::new(self.clone())
+    }
+}
+
+impl Clone for Box<dyn Randomizer> {
+    fn clone(&self) -> Self {
+        self.box_clone()
+    }
+}
+
+/// One of the built in randomizers partway through its sequence, in a form that can be saved.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum RandomizerState {
+    /// A [`Bag`] of any size.
+    Bag(Bag),
+    /// A [`PureRandom`].
+    PureRandom(PureRandom),
+    /// A [`Nes`].
+    Nes(Nes),
+    /// A [`History`], as in TGM and TGM2.
+    History(History),
+    /// A [`Tgm3`].
+    Tgm3(Tgm3),
+}
+
+impl RandomizerState {
+    /// Picks up dealing where the saved randomizer left off.
+    pub fn into_randomizer(self) -> Box<dyn Randomizer> {
+        match self {
+            Self::Bag(bag) => Box::new(bag),
+            Self::PureRandom(randomizer) => Box::new(randomizer),
+            Self::Nes(randomizer) => Box::new(randomizer),
+            Self::History(randomizer) => Box::new(randomizer),
+            Self::Tgm3(randomizer) => Box::new(randomizer),
+        }
+    }
+
+    /// Whether this could have come from a real randomizer. Deserializing skips the checks
+    /// constructors make, and some broken states would panic dealing the next piece.
+    pub fn is_valid(&self) -> bool {
+        match self {
+            Self::Bag(bag) => bag.copies > 0,
+            Self::PureRandom(_) | Self::Nes(_) => true,
+            Self::History(randomizer) => randomizer.rolls > 0 && randomizer.history.len() == 4,
+            Self::Tgm3(randomizer) => !randomizer.pool.is_empty()
+                && !randomizer.droughts.is_empty()
+                && randomizer.history.len() == 4,
+        }
+    }
+}
+
+/// Each of the built in randomizers, to pick one by name and seed it later.
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub enum RandomizerKind {
+    /// The guideline 7-bag.
+    Bag7,
+    /// Two of every piece to a bag.
+    Bag14,
+    /// No memory at all.
+    PureRandom,
+    /// One reroll against the last piece.
+    Nes,
+    /// Four rolls against a history of four.
+    Tgm,
+    /// Six rolls against a history of four.
+    Tgm2,
+    /// The pool and drought based TGM3 randomizer.
+    Tgm3,
+}
+
+impl RandomizerKind {
+    /// A new randomizer of this kind, dealing pieces seeded by `seed`.
+    pub fn build(self, seed: u64) -> Box<dyn Randomizer> {
+        match self {
+            Self::Bag7 => Box::new(Bag::seven(seed)),
+            Self::Bag14 => Box::new(Bag::fourteen(seed)),
+            Self::PureRandom => Box::new(PureRandom::new(seed)),
+            Self::Nes => Box::new(Nes::new(seed)),
+            Self::Tgm => Box::new(History::tgm(seed)),
+            Self::Tgm2 => Box::new(History::tgm2(seed)),
+            Self::Tgm3 => Box::new(Tgm3::new(seed)),
+        }
+    }
+}
+
+fn seeded(seed: u64) -> ChaCha8Rng {
+    ChaCha8Rng::seed_from_u64(seed)
+}
+
+fn random_kind(rng: &mut ChaCha8Rng) -> Kind {
+    *Kind::ALL.choose(rng).unwrap()
+}
+
+/// Shuffles a number of copies of every piece together and deals them out in order.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Bag {
+    rng: ChaCha8Rng,
+    copies: usize,
+    bag: Vec<Kind>,
+}
+
+impl Bag {
+    /// A bag of `copies` of every piece. Panics if that's none.
+    pub fn new(copies: usize, seed: u64) -> Self {
+        assert!(copies > 0, "A bag needs at least one copy of each piece");
+        Self {
+            rng: seeded(seed),
+            copies,
+            bag: Vec::new(),
+        }
+    }
+
+    /// The guideline 7-bag, one of each piece.
+    pub fn seven(seed: u64) -> Self {
+        Self::new(1, seed)
+    }
+
+    /// Two of each piece to a bag.
+    pub fn fourteen(seed: u64) -> Self {
+        Self::new(2, seed)
+    }
+}
+
+impl Randomizer for Bag {
+    fn next(&mut self) -> Kind {
+        if self.bag.is_empty() {
+            for _ in 0..self.copies {
+                self.bag.extend_from_slice(Kind::ALL.as_slice());
+            }
+            self.bag.shuffle(&mut self.rng);
+        }
+        self.bag.pop().unwrap()
+    }
+
+    fn state(&self) -> Option<RandomizerState> {
+        Some(RandomizerState::Bag(self.clone()))
+    }
+}
+
+/// Every piece is independent of the last.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PureRandom {
+    rng: ChaCha8Rng,
+}
+
+impl PureRandom {
+    /// Deals pieces seeded by `seed`.
+    pub fn new(seed: u64) -> Self {
+        Self { rng: seeded(seed) }
+    }
+}
+
+impl Randomizer for PureRandom {
+    fn next(&mut self) -> Kind {
+        random_kind(&mut self.rng)
+    }
+
+    fn state(&self) -> Option<RandomizerState> {
+        Some(RandomizerState::PureRandom(self.clone()))
+    }
+}
+
+/// The NES randomizer: rolls one of eight outcomes, and rerolls once among
+/// the seven pieces if it got the dummy eighth outcome or a repeat.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Nes {
+    rng: ChaCha8Rng,
+    previous: Option<Kind>,
+}
+
+impl Nes {
+    /// Deals pieces seeded by `seed`, with no previous piece to avoid.
+    pub fn new(seed: u64) -> Self {
+        Self {
+            rng: seeded(seed),
+            previous: None,
+        }
+    }
+}
+
+impl Randomizer for Nes {
+    fn next(&mut self) -> Kind {
+        let roll = self.rng.gen_range(0..=Kind::ALL.len());
+        let kind = match Kind::ALL.get(roll) {
+            Some(&kind) if Some(kind) != self.previous => kind,
+            _ => random_kind(&mut self.rng),
+        };
+        self.previous = Some(kind);
+        kind
+    }
+
+    fn state(&self) -> Option<RandomizerState> {
+        Some(RandomizerState::Nes(self.clone()))
+    }
+}
+
+/// The Grand Master randomizers: rerolls a limited number of times to avoid
+/// any of the last four pieces. The first piece is never S, Z or O.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct History {
+    rng: ChaCha8Rng,
+    rolls: usize,
+    history: VecDeque<Kind>,
+    dealt_first: bool,
+}
+
+const FIRST_PIECES: [Kind;4] = [Kind::I, Kind::J, Kind::L, Kind::T];
+
+impl History {
+    /// Rolls up to `rolls` times against `history`, oldest first. Panics if `rolls` is 0.
+    pub fn new(rolls: usize, history: [Kind;4], seed: u64) -> Self {
+        assert!(rolls > 0, "The history randomizer needs at least one roll");
+        Self {
+            rng: seeded(seed),
+            rolls,
+            history: VecDeque::from(history),
+            dealt_first: false,
+        }
+    }
+
+    /// Four rolls, starting with a history of Z Z Z Z.
+    pub fn tgm(seed: u64) -> Self {
+        Self::new(4, [Kind::Z;4], seed)
+    }
+
+    /// Six rolls, starting with a history of Z S S Z.
+    pub fn tgm2(seed: u64) -> Self {
+        Self::new(6, [Kind::Z, Kind::S, Kind::S, Kind::Z], seed)
+    }
+
+    fn roll(&mut self) -> Kind {
+        if !self.dealt_first {
+            self.dealt_first = true;
+            return *FIRST_PIECES.choose(&mut self.rng).unwrap();
+        }
+
+        let mut kind = random_kind(&mut self.rng);
+        for _ in 1..self.rolls {
+            if !self.history.contains(&kind) {
+                break;
+            }
+            kind = random_kind(&mut self.rng);
+        }
+        kind
+    }
+}
+
+impl Randomizer for History {
+    fn next(&mut self) -> Kind {
+        let kind = self.roll();
+        self.history.pop_front();
+        self.history.push_back(kind);
+        kind
+    }
+
+    fn state(&self) -> Option<RandomizerState> {
+        Some(RandomizerState::History(self.clone()))
+    }
+}
+
+/// The Terror-Instinct randomizer from TGM3: six rolls against a history of
+/// four, drawing from a pool of 35 pieces which is topped up with whichever
+/// piece has gone the longest without being dealt.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Tgm3 {
+    rng: ChaCha8Rng,
+    pool: Vec<Kind>,
+    history: VecDeque<Kind>,
+    /// Longest drought first.
+    droughts: Vec<Kind>,
+    dealt_first: bool,
+}
+
+impl Tgm3 {
+    const ROLLS: usize = 6;
+    const COPIES: usize = 5;
+
+    /// Deals pieces seeded by `seed`, starting from a full pool.
+    pub fn new(seed: u64) -> Self {
+        let pool = Kind::ALL.iter()
+            .flat_map(|&kind| [kind;Self::COPIES])
+            .collect();
+
+        Self {
+            rng: seeded(seed),
+            pool,
+            history: VecDeque::from([Kind::S, Kind::Z, Kind::S, Kind::Z]),
+            droughts: vec![Kind::J, Kind::I, Kind::Z, Kind::L, Kind::O, Kind::T, Kind::S],
+            dealt_first: false,
+        }
+    }
+
+    fn roll(&mut self) -> Kind {
+        if !self.dealt_first {
+            self.dealt_first = true;
+            return *FIRST_PIECES.choose(&mut self.rng).unwrap();
+        }
+
+        let mut index = 0;
+        for roll in 0..Self::ROLLS {
+            index = self.rng.gen_range(0..self.pool.len());
+            let kind = self.pool[index];
+            if !self.history.contains(&kind) || roll == Self::ROLLS - 1 {
+                break;
+            }
+            // TGM3 also tops the pool up on rejected rolls, nudging it towards droughted pieces
+            self.pool[index] = self.droughts[0];
+        }
+
+        let kind = self.pool[index];
+        self.droughts.retain(|&droughted| droughted != kind);
+        self.droughts.push(kind);
+        self.pool[index] = self.droughts[0];
+        kind
+    }
+}
+
+impl Randomizer for Tgm3 {
+    fn next(&mut self) -> Kind {
+        let kind = self.roll();
+        self.history.pop_front();
+        self.history.push_back(kind);
+        kind
+    }
+
+    fn state(&self) -> Option<RandomizerState> {
+        Some(RandomizerState::Tgm3(self.clone()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn deal(randomizer: &mut dyn Randomizer, count: usize) -> Vec<Kind> {
+        (0..count).map(|_| randomizer.next()).collect()
+    }
+
+    fn count(kinds: &[Kind], kind: Kind) -> usize {
+        kinds.iter().filter(|&&dealt| dealt == kind).count()
+    }
+
+    #[test]
+    fn same_seed_same_sequence() {
+        let kinds = [
+            RandomizerKind::Bag7,
+            RandomizerKind::Bag14,
+            RandomizerKind::PureRandom,
+            RandomizerKind::Nes,
+            RandomizerKind::Tgm,
+            RandomizerKind::Tgm2,
+            RandomizerKind::Tgm3,
+        ];
+
+        for kind in kinds {
+            let first = deal(&mut *kind.build(1234), 200);
+            assert_eq!(first, deal(&mut *kind.build(1234), 200));
+            assert_ne!(first, deal(&mut *kind.build(4321), 200));
+        }
+    }
+
+    #[test]
+    fn clones_deal_the_same_pieces() {
+        let mut randomizer = RandomizerKind::Tgm3.build(8);
+        deal(&mut *randomizer, 50);
+        let mut clone = randomizer.clone();
+        assert_eq!(deal(&mut *randomizer, 100), deal(&mut *clone, 100));
+    }
+
+    #[test]
+    fn bags_deal_every_piece() {
+        for copies in [1, 2] {
+            let mut bag = Bag::new(copies, 99);
+            let size = copies * Kind::ALL.len();
+            for _ in 0..20 {
+                let dealt = deal(&mut bag, size);
+                for kind in Kind::ALL {
+                    assert_eq!(count(&dealt, kind), copies, "{:?} in {:?}", kind, dealt);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn history_never_starts_with_snake_or_o() {
+        for seed in 0..200 {
+            for mut randomizer in [
+                Box::new(History::tgm(seed)) as Box<dyn Randomizer>,
+                Box::new(History::tgm2(seed)),
+                Box::new(Tgm3::new(seed)),
+            ] {
+                let first = randomizer.next();
+                assert!(FIRST_PIECES.contains(&first), "seed {} started with {:?}", seed, first);
+            }
+        }
+    }
+
+    #[test]
+    fn history_avoids_repeats() {
+        let dealt = deal(&mut History::tgm2(7), 7000);
+        let repeats = dealt.windows(2).filter(|pair| pair[0] == pair[1]).count();
+
+        // A pure random sequence repeats about one time in seven
+        assert!(repeats < dealt.len() / 50, "{} repeats", repeats);
+    }
+
+    #[test]
+    fn tgm3_pool_stays_full() {
+        let mut randomizer = Tgm3::new(5);
+        deal(&mut randomizer, 1000);
+        assert_eq!(randomizer.pool.len(), Kind::ALL.len() * Tgm3::COPIES);
+        assert_eq!(randomizer.droughts.len(), Kind::ALL.len());
+    }
+
+    #[test]
+    fn nes_rarely_repeats() {
+        let dealt = deal(&mut Nes::new(3), 7000);
+        let repeats = dealt.windows(2).filter(|pair| pair[0] == pair[1]).count();
+
+        // Only the reroll can repeat: (1/8 + 1/8) * 1/7 of the time
+        assert!(repeats < dealt.len() / 20, "{} repeats", repeats);
+    }
+}
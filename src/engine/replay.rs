@@ -0,0 +1,582 @@
+use std::{fmt, time::Duration};
+use bincode::Options;
+use serde::{Serialize, Deserialize};
+use super::{
+    Engine, Matrix, MoveKind, RotateKind, TopOut, Phase,
+    randomizer::RandomizerKind,
+    rotation_system::RotationSystemKind,
+    rules::Rules,
+};
+
+/// Everything that decides how a game plays out, apart from the player.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Setup {
+    /// Seeds the randomizer.
+    pub seed: u64,
+    /// How pieces are dealt.
+    pub randomizer: RandomizerKind,
+    /// How pieces are shaped and kicked.
+    pub rotation_system: RotationSystemKind,
+    /// Everything else about how the game plays.
+    pub rules: Rules,
+    /// Columns in the matrix.
+    pub width: usize,
+    /// Rows in view, with as many again hidden above.
+    pub visible_height: usize,
+}
+
+impl Setup {
+    /// The standard game, dealt from `seed`.
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            randomizer: RandomizerKind::Bag7,
+            rotation_system: RotationSystemKind::Srs,
+            rules: Rules::default(),
+            width: Matrix::DEFAULT_WIDTH,
+            visible_height: Matrix::DEFAULT_VISIBLE_HEIGHT,
+        }
+    }
+
+    /// A fresh engine for this setup. Engines from the same setup, given the same actions
+    /// on the same frames, play out exactly the same.
+    pub fn engine(&self) -> Engine {
+        Engine {
+            matrix: Matrix::new(self.width, self.visible_height),
+            randomizer: self.randomizer.build(self.seed),
+            rotation_system: self.rotation_system.build(),
+            rules: self.rules.clone(),
+            ..Engine::new()
+        }
+    }
+}
+
+/// Something the player does to the engine.
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub enum Action {
+    /// [`Engine::move_cursor`].
+    Move(MoveKind),
+    /// [`Engine::rotate_cursor`].
+    Rotate(RotateKind),
+    /// [`Engine::hold`].
+    Hold,
+    /// [`Engine::hard_drop`].
+    HardDrop,
+    /// [`Engine::set_soft_drop`], turning it on or off.
+    SoftDrop(bool),
+    /// [`Engine::undo`].
+    Undo,
+    /// [`Engine::redo`].
+    Redo,
+}
+
+impl Action {
+    /// Does this to `engine`. Moves, rotations, undos and redos report whether they were possible,
+    /// everything else counts as done unless it ends the game.
+    pub fn apply(self, engine: &mut Engine) -> Result<bool, TopOut> {
+        match self {
+            Action::Move(kind) => Ok(engine.move_cursor(kind).is_ok()),
+            Action::Rotate(kind) => Ok(engine.rotate_cursor(kind).is_ok()),
+            Action::Hold => engine.hold().map(|()| true),
+            Action::HardDrop => engine.hard_drop().map(|()| true),
+            Action::SoftDrop(active) => {
+                engine.set_soft_drop(active);
+                Ok(true)
+            }
+            Action::Undo => Ok(engine.undo().is_ok()),
+            Action::Redo => Ok(engine.redo().is_ok()),
+        }
+    }
+}
+
+/// An action, and the frame it happened at the start of.
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub struct TimedAction {
+    /// Counting from 0, the first frame of the game.
+    pub frame: u32,
+    /// What the player did.
+    pub action: Action,
+}
+
+/// A recorded game: how it was set up, and what the player did when.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Replay {
+    /// How the game was set up.
+    pub setup: Setup,
+    /// How much time passes each frame.
+    pub timestep: Duration,
+    /// How many frames the game ran for.
+    pub frames: u32,
+    /// Everything the player did, in order. Actions happen at the start of their frame, before the engine updates.
+    pub actions: Vec<TimedAction>,
+}
+
+/// The binary form after its header, with each action's frame counted from the one before.
+#[derive(Serialize, Deserialize)]
+struct Body {
+    setup: Setup,
+    timestep: Duration,
+    frames: u32,
+    actions: Vec<(u32, Action)>,
+}
+
+fn encoding() -> impl Options {
+    // Variable length integers keep the small frame gaps to a byte or so
+    bincode::DefaultOptions::new()
+}
+
+impl Replay {
+    const MAGIC: [u8;4] = *b"TEHR";
+    /// The binary format version written, and the only one read.
+    pub const VERSION: u16 = 2;
+    /// Bigger than any game needs, and small enough that the matrix can always be allocated.
+    const MAX_SIZE: usize = 1000;
+
+    /// An empty recording of a game set up by `setup`, with frames `timestep` long.
+    pub fn new(setup: Setup, timestep: Duration) -> Self {
+        Self {
+            setup,
+            timestep,
+            frames: 0,
+            actions: Vec::new(),
+        }
+    }
+
+    /// The compact binary form: a magic number and format version, then the replay itself.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut last_frame = 0;
+        let actions = self.actions.iter()
+            .map(|timed| (timed.frame - std::mem::replace(&mut last_frame, timed.frame), timed.action))
+            .collect();
+        let body = Body {
+            setup: self.setup.clone(),
+            timestep: self.timestep,
+            frames: self.frames,
+            actions,
+        };
+
+        let mut bytes = Self::MAGIC.to_vec();
+        bytes.extend(Self::VERSION.to_le_bytes());
+        encoding().serialize_into(&mut bytes, &body).expect("Replays can always be encoded");
+        bytes
+    }
+
+    /// Reads the binary form written by [`Replay::to_bytes`].
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ReplayError> {
+        let rest = bytes.strip_prefix(&Self::MAGIC).ok_or(ReplayError::NotAReplay)?;
+        let (&version, rest) = rest.split_first_chunk().ok_or(ReplayError::NotAReplay)?;
+        let version = u16::from_le_bytes(version);
+        if version != Self::VERSION {
+            return Err(ReplayError::UnsupportedVersion(version));
+        }
+
+        let body: Body = encoding().deserialize(rest).map_err(ReplayError::Corrupt)?;
+        let mut frame = 0u32;
+        let mut actions = Vec::with_capacity(body.actions.len());
+        for (gap, action) in body.actions {
+            frame = frame.checked_add(gap).ok_or(ReplayError::Invalid("action frames overflow"))?;
+            actions.push(TimedAction { frame, action });
+        }
+
+        Self {
+            setup: body.setup,
+            timestep: body.timestep,
+            frames: body.frames,
+            actions,
+        }.validated()
+    }
+
+    /// A readable form for debugging, which [`Replay::parse`] also accepts.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("Replays can always be encoded")
+    }
+
+    /// Reads the JSON form written by [`Replay::to_json`].
+    pub fn from_json(text: &str) -> Result<Self, ReplayError> {
+        serde_json::from_str::<Self>(text).map_err(ReplayError::Json)?.validated()
+    }
+
+    /// Reads either form, telling them apart by the binary form's magic number.
+    pub fn parse(bytes: &[u8]) -> Result<Self, ReplayError> {
+        if bytes.starts_with(&Self::MAGIC) {
+            return Self::from_bytes(bytes);
+        }
+        let text = std::str::from_utf8(bytes).map_err(|_| ReplayError::NotAReplay)?;
+        Self::from_json(text)
+    }
+
+    /// Rules out anything that would panic or misbehave when played back.
+    fn validated(self) -> Result<Self, ReplayError> {
+        if self.setup.width < Matrix::MIN_WIDTH || self.setup.visible_height == 0 {
+            return Err(ReplayError::Invalid("matrix is too small"));
+        }
+        if self.setup.width > Self::MAX_SIZE || self.setup.visible_height > Self::MAX_SIZE {
+            return Err(ReplayError::Invalid("matrix is too big"));
+        }
+        if self.timestep.is_zero() {
+            return Err(ReplayError::Invalid("timestep is zero"));
+        }
+        if !self.actions.windows(2).all(|pair| pair[0].frame <= pair[1].frame) {
+            return Err(ReplayError::Invalid("actions are out of order"));
+        }
+        // The last action can come after the last frame, if it ended the game
+        if self.actions.last().is_some_and(|last| last.frame > self.frames) {
+            return Err(ReplayError::Invalid("actions run past the end"));
+        }
+        Ok(self)
+    }
+}
+
+/// Why a replay couldn't be read.
+#[derive(Debug)]
+pub enum ReplayError {
+    /// Neither the binary nor the JSON form.
+    NotAReplay,
+    /// Written by a different version of the game.
+    UnsupportedVersion(u16),
+    /// The binary form, but cut short or garbled.
+    Corrupt(bincode::Error),
+    /// Looked like JSON, but not a replay in it.
+    Json(serde_json::Error),
+    /// Readable, but can't be played back.
+    Invalid(&'static str),
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplayError::NotAReplay => write!(f, "Not a replay file"),
+            ReplayError::UnsupportedVersion(version) => write!(
+                f,
+                "Replay is format version {}, but only version {} is supported",
+                version,
+                Replay::VERSION,
+            ),
+            ReplayError::Corrupt(error) => write!(f, "Corrupt replay: {}", error),
+            ReplayError::Json(error) => write!(f, "Invalid replay JSON: {}", error),
+            ReplayError::Invalid(reason) => write!(f, "Invalid replay: {}", reason),
+        }
+    }
+}
+
+/// Records a game by being the way actions and time reach its engine.
+#[derive(Clone, Debug)]
+pub struct Recorder {
+    replay: Replay,
+}
+
+impl Recorder {
+    /// Starts recording a new game, returning the engine to play it on.
+    pub fn start(setup: Setup, timestep: Duration) -> (Self, Engine) {
+        let engine = setup.engine();
+        (Self { replay: Replay::new(setup, timestep) }, engine)
+    }
+
+    /// Applies `action` to `engine` on the current frame.
+    pub fn act(&mut self, engine: &mut Engine, action: Action) -> Result<bool, TopOut> {
+        let result = action.apply(engine);
+        // Actions that weren't possible change nothing, so there's no need to keep them
+        if result != Ok(false) {
+            self.replay.actions.push(TimedAction { frame: self.replay.frames, action });
+        }
+        result
+    }
+
+    /// Moves `engine` on by a frame.
+    pub fn step(&mut self, engine: &mut Engine) -> Result<(), TopOut> {
+        self.replay.frames += 1;
+        engine.update(self.replay.timestep)
+    }
+
+    /// Everything recorded so far.
+    pub fn replay(&self) -> &Replay {
+        &self.replay
+    }
+
+    /// Stops recording and hands back the replay.
+    pub fn finish(self) -> Replay {
+        self.replay
+    }
+}
+
+/// Plays a replay back, with seeking in either direction.
+#[derive(Clone)]
+pub struct Player {
+    replay: Replay,
+    engine: Engine,
+    frame: u32,
+    /// How many actions have been applied.
+    next_action: usize,
+    /// The engine at the start of every [`Player::SNAPSHOT_INTERVAL`]th frame reached so far.
+    snapshots: Vec<(Engine, usize)>,
+}
+
+impl Player {
+    /// Ten seconds apart at 60 frames a second.
+    pub const SNAPSHOT_INTERVAL: u32 = 600;
+
+    /// Starts playing `replay` from the beginning.
+    pub fn new(replay: Replay) -> Self {
+        let engine = replay.setup.engine();
+        Self {
+            snapshots: vec![(engine.clone(), 0)],
+            engine,
+            frame: 0,
+            next_action: 0,
+            replay,
+        }
+    }
+
+    /// The replay being played.
+    pub fn replay(&self) -> &Replay {
+        &self.replay
+    }
+
+    /// The game as of the current frame.
+    pub fn engine(&self) -> &Engine {
+        &self.engine
+    }
+
+    /// The engine being played back, for previewing its queue and draining its events.
+    /// Anything else done to it will throw the rest of the playback off.
+    pub fn engine_mut(&mut self) -> &mut Engine {
+        &mut self.engine
+    }
+
+    /// The frame about to be played.
+    pub fn frame(&self) -> u32 {
+        self.frame
+    }
+
+    /// How the game ended, if it has by this point in the replay.
+    pub fn game_over(&self) -> Option<TopOut> {
+        match self.engine.phase {
+            Phase::ToppedOut(top_out) => Some(top_out),
+            _ => None,
+        }
+    }
+
+    /// Whether every frame and action has been played.
+    pub fn finished(&self) -> bool {
+        self.frame >= self.replay.frames && self.next_action == self.replay.actions.len()
+    }
+
+    /// Plays the next frame, unless the replay has finished.
+    pub fn step(&mut self) {
+        while let Some(timed) = self.replay.actions.get(self.next_action).filter(|timed| timed.frame == self.frame) {
+            self.next_action += 1;
+            // Topping out doesn't stop the rest, since in practice an undo can bring the game back
+            let _ = timed.action.apply(&mut self.engine);
+        }
+        if self.frame >= self.replay.frames {
+            return;
+        }
+
+        self.frame += 1;
+        // A game that's topped out still needs snapshots, in case it's undone later on
+        let _ = self.engine.update(self.replay.timestep);
+
+        let reached = (self.frame / Self::SNAPSHOT_INTERVAL) as usize;
+        if self.frame.is_multiple_of(Self::SNAPSHOT_INTERVAL) && reached == self.snapshots.len() {
+            self.snapshots.push((self.engine.clone(), self.next_action));
+        }
+    }
+
+    /// Jumps to the start of `frame`, from the nearest snapshot before it if that's quicker.
+    /// Events from the frames skipped over are dropped.
+    pub fn seek(&mut self, frame: u32) {
+        let frame = frame.min(self.replay.frames);
+        let nearest = (frame / Self::SNAPSHOT_INTERVAL).min(self.snapshots.len() as u32 - 1);
+        let snapshot_frame = nearest * Self::SNAPSHOT_INTERVAL;
+
+        if frame < self.frame || snapshot_frame > self.frame {
+            let (engine, next_action) = &self.snapshots[nearest as usize];
+            self.engine = engine.clone();
+            self.next_action = *next_action;
+            self.frame = snapshot_frame;
+        }
+
+        while self.frame < frame && !self.finished() {
+            self.step();
+        }
+        self.engine.events.clear();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::engine::rules::Mode;
+
+    const FRAME: Duration = Duration::from_nanos(1_000_000_000 / 60);
+
+    /// Plays a game with a simple repeating pattern of moves, until it tops out.
+    fn record_a_game() -> (Replay, Engine) {
+        let (mut recorder, mut engine) = Recorder::start(Setup::new(42), FRAME);
+        for frame in 0..20_000u32 {
+            let piece = frame / 40;
+            let action = match frame % 40 {
+                0 => Some(Action::Rotate(if piece % 2 == 0 { RotateKind::Clockwise } else { RotateKind::Half })),
+                5..=8 if piece % 3 != 0 => Some(Action::Move(if piece % 2 == 0 { MoveKind::Left } else { MoveKind::Right })),
+                10 if piece % 7 == 3 => Some(Action::Hold),
+                12 => Some(Action::SoftDrop(true)),
+                20 => Some(Action::SoftDrop(false)),
+                30 => Some(Action::HardDrop),
+                _ => None,
+            };
+
+            let result = match action {
+                Some(action) => recorder.act(&mut engine, action).map(|_| ()),
+                None => Ok(()),
+            };
+            if result.and_then(|()| recorder.step(&mut engine)).is_err() {
+                break;
+            }
+        }
+        (recorder.finish(), engine)
+    }
+
+    fn assert_same_game(a: &Engine, b: &Engine) {
+        assert_eq!(a.matrix(), b.matrix());
+        assert_eq!(a.stats(), b.stats());
+        assert_eq!(a.phase(), b.phase());
+        assert_eq!(a.cursor(), b.cursor());
+        assert_eq!(a.held_piece(), b.held_piece());
+    }
+
+    #[test]
+    fn playback_matches_the_recording() {
+        let (replay, recorded) = record_a_game();
+        assert!(replay.frames > 900, "game only lasted {} frames", replay.frames);
+
+        let mut player = Player::new(replay);
+        while !player.finished() {
+            player.step();
+        }
+        assert!(player.game_over().is_some());
+        assert_same_game(player.engine(), &recorded);
+    }
+
+    #[test]
+    fn undos_play_back_too() {
+        let setup = Setup {
+            rules: Rules { mode: Mode::Practice, ..Rules::default() },
+            ..Setup::new(5)
+        };
+        let (mut recorder, mut engine) = Recorder::start(setup, FRAME);
+        for frame in 0..3000u32 {
+            let action = match frame % 30 {
+                0 => Some(Action::HardDrop),
+                15 if frame % 90 == 15 => Some(Action::Undo),
+                20 if frame % 180 == 20 => Some(Action::Redo),
+                _ => None,
+            };
+            let result = match action {
+                Some(action) => recorder.act(&mut engine, action).map(|_| ()),
+                None => recorder.step(&mut engine),
+            };
+            // Keep going past topping out, as a player drilling would
+            if result.is_err() {
+                assert_eq!(recorder.act(&mut engine, Action::Undo), Ok(true));
+            }
+        }
+        let replay = recorder.finish();
+        assert!(engine.stats().undos > 10);
+
+        let mut player = Player::new(replay);
+        while !player.finished() {
+            player.step();
+        }
+        assert_same_game(player.engine(), &engine);
+    }
+
+    #[test]
+    fn seeking_matches_playing_through() {
+        let (replay, _) = record_a_game();
+        let mut seeker = Player::new(replay.clone());
+
+        for frame in [900, 300, 601, 599, 0, 640] {
+            let mut player = Player::new(replay.clone());
+            while player.frame() < frame && !player.finished() {
+                player.step();
+            }
+
+            seeker.seek(frame);
+            assert_eq!(seeker.frame(), frame);
+            assert_same_game(seeker.engine(), player.engine());
+        }
+    }
+
+    #[test]
+    fn seeking_past_a_top_out() {
+        let setup = Setup {
+            rules: Rules { mode: Mode::Practice, ..Rules::default() },
+            ..Setup::new(8)
+        };
+        let (mut recorder, mut engine) = Recorder::start(setup, FRAME);
+        let mut topped_out_at = None;
+        for frame in 0..2000u32 {
+            if matches!(engine.phase(), Phase::ToppedOut(_)) {
+                // Sit there topped out past a snapshot before taking it back
+                let since = *topped_out_at.get_or_insert(frame);
+                if frame > since.max(Player::SNAPSHOT_INTERVAL) {
+                    assert_eq!(recorder.act(&mut engine, Action::Undo), Ok(true));
+                }
+            } else if frame % 30 == 0 {
+                let _ = recorder.act(&mut engine, Action::HardDrop);
+            }
+            let _ = recorder.step(&mut engine);
+        }
+        let replay = recorder.finish();
+        assert!(topped_out_at.is_some_and(|frame| frame < Player::SNAPSHOT_INTERVAL));
+
+        let mut seeker = Player::new(replay.clone());
+        seeker.seek(1900);
+        assert_eq!(seeker.snapshots.len(), 4);
+
+        let mut player = Player::new(replay);
+        while player.frame() < 1900 {
+            player.step();
+        }
+        assert_same_game(seeker.engine(), player.engine());
+    }
+
+    #[test]
+    fn binary_and_json_round_trip() {
+        let (replay, _) = record_a_game();
+
+        let bytes = replay.to_bytes();
+        assert_eq!(Replay::from_bytes(&bytes).unwrap(), replay);
+        assert_eq!(Replay::parse(&bytes).unwrap(), replay);
+        assert_eq!(Replay::parse(replay.to_json().as_bytes()).unwrap(), replay);
+
+        // Frame gaps and action tags fit in a few bytes each
+        assert!(bytes.len() < 100 + replay.actions.len() * 4, "{} bytes", bytes.len());
+    }
+
+    #[test]
+    fn other_files_are_rejected() {
+        let (replay, _) = record_a_game();
+        let bytes = replay.to_bytes();
+
+        assert!(matches!(Replay::parse(b"\x89PNG\r\n"), Err(ReplayError::NotAReplay)));
+        assert!(matches!(Replay::from_bytes(&bytes[..5]), Err(ReplayError::NotAReplay)));
+        assert!(matches!(Replay::from_bytes(&bytes[..bytes.len() - 1]), Err(ReplayError::Corrupt(_))));
+
+        let mut future = bytes.clone();
+        future[4..6].copy_from_slice(&(Replay::VERSION + 1).to_le_bytes());
+        assert!(matches!(
+            Replay::from_bytes(&future),
+            Err(ReplayError::UnsupportedVersion(version)) if version == Replay::VERSION + 1,
+        ));
+
+        let mut narrow = replay.clone();
+        narrow.setup.width = 2;
+        assert!(matches!(Replay::parse(&narrow.to_bytes()), Err(ReplayError::Invalid(_))));
+
+        let mut huge = replay;
+        huge.setup.width = usize::MAX / 4;
+        huge.setup.visible_height = 8;
+        assert!(matches!(Replay::parse(&huge.to_bytes()), Err(ReplayError::Invalid(_))));
+    }
+}
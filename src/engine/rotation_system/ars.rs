@@ -0,0 +1,93 @@
+use cgmath::EuclideanSpace;
+use super::{RotationSystem, RotationSystemKind, Offset, Matrix, Piece, Kind, Rotation, srs};
+use crate::engine::Coordinate;
+
+/// Arika's rotation system from the Tetris: The Grand Master series.
+/// Pieces sit at the bottom of their box and spawn flat side up.
+#[derive(Clone, Copy, Debug)]
+pub struct Ars;
+
+const NO_KICKS: [(isize, isize);1] = [(0, 0)];
+const BASIC_KICKS: [(isize, isize);3] = [(0, 0), (1, 0), (-1, 0)];
+
+impl RotationSystem for Ars {
+    fn shape(&self, kind: Kind, rotation: Rotation) -> [Offset;Piece::CELL_COUNT] {
+        let down = Offset::new(0, -1);
+        match (kind, rotation) {
+            (Kind::O, _) => srs::shape(kind, rotation).map(|cell| cell + down),
+            (Kind::I, Rotation::N | Rotation::S) => srs::shape(kind, Rotation::N),
+            (Kind::I, Rotation::E | Rotation::W) => srs::shape(kind, Rotation::E),
+            (Kind::S, Rotation::N | Rotation::S) => srs::shape(kind, Rotation::S),
+            (Kind::S, Rotation::E | Rotation::W) => srs::shape(kind, Rotation::W),
+            (Kind::Z, Rotation::N | Rotation::S) => srs::shape(kind, Rotation::S),
+            (Kind::Z, Rotation::E | Rotation::W) => srs::shape(kind, Rotation::E),
+            (_, Rotation::N) => srs::shape(kind, rotation).map(|cell| cell + down),
+            _ => srs::shape(kind, rotation),
+        }
+    }
+
+    fn spawn_rotation(&self, kind: Kind) -> Rotation {
+        match kind {
+            Kind::J | Kind::L | Kind::T => Rotation::S,
+            _ => Rotation::N,
+        }
+    }
+
+    fn kicks(&self, matrix: &Matrix, piece: &Piece, to: Rotation) -> Vec<Offset> {
+        let tests: &[(isize, isize)] = match piece.kind {
+            Kind::O | Kind::I => &NO_KICKS,
+            Kind::J | Kind::L | Kind::T if self.center_column_blocked(matrix, piece, to) => &NO_KICKS,
+            _ => &BASIC_KICKS,
+        };
+
+        tests.iter().copied().map(Offset::from).collect()
+    }
+
+    fn kind(&self) -> Option<RotationSystemKind> {
+        Some(RotationSystemKind::Ars)
+    }
+}
+
+impl Ars {
+    /// The centre column rule: scanning the rotated piece's cells from the top
+    /// row down and left to right, if the first one that overlaps the stack is
+    /// in the middle column, the piece isn't allowed to kick.
+    fn center_column_blocked(&self, matrix: &Matrix, piece: &Piece, to: Rotation) -> bool {
+        let mut cells = self.shape(piece.kind, to);
+        cells.sort_by_key(|cell| (-cell.y, cell.x));
+
+        cells.into_iter()
+            .find(|&cell| {
+                let Some(coord) = (piece.position + cell).cast::<usize>() else {
+                    return false;
+                };
+                matrix.is_occupied(Coordinate::from_vec(coord))
+            })
+            .is_some_and(|cell| cell.x == 1)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::engine::Color;
+
+    #[test]
+    fn center_column_stops_kicks() {
+        let piece = Piece { kind: Kind::T, position: Offset::new(3, 0), rotation: Rotation::S };
+
+        let mut matrix = Matrix::blank();
+        matrix[Coordinate::new(4, 2)] = Some(Color::Red);
+        assert_eq!(Ars.kicks(&matrix, &piece, Rotation::W), [Offset::new(0, 0)]);
+
+        let mut matrix = Matrix::blank();
+        matrix[Coordinate::new(3, 1)] = Some(Color::Red);
+        assert_eq!(Ars.kicks(&matrix, &piece, Rotation::W).len(), BASIC_KICKS.len());
+    }
+
+    #[test]
+    fn i_never_kicks() {
+        let piece = Piece { kind: Kind::I, position: Offset::new(3, 0), rotation: Rotation::N };
+        assert_eq!(Ars.kicks(&Matrix::blank(), &piece, Rotation::E), [Offset::new(0, 0)]);
+    }
+}
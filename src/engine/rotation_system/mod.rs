@@ -0,0 +1,120 @@
+use std::{fmt::Debug, sync::Arc};
+use serde::{Serialize, Deserialize};
+use super::{Offset, Matrix, piece::{Piece, Kind, Rotation}};
+
+mod srs;
+mod ars;
+mod nrs;
+
+pub use self::{srs::Srs, ars::Ars, nrs::Nrs};
+
+/// A rule set for how pieces look in each orientation and how they get out of tight spots.
+pub trait RotationSystem: Debug + Send + Sync {
+    /// Cells of `kind` in the given orientation, relative to the bottom left of its bounding box.
+    fn shape(&self, kind: Kind, rotation: Rotation) -> [Offset;Piece::CELL_COUNT];
+
+    /// Which way `kind` faces when it spawns.
+    fn spawn_rotation(&self, _kind: Kind) -> Rotation {
+        Rotation::N
+    }
+
+    /// Offsets to test, in order, when `piece` turns to face `to`.
+    /// The first one that doesn't clip is used.
+    fn kicks(&self, matrix: &Matrix, piece: &Piece, to: Rotation) -> Vec<Offset>;
+
+    /// Index of the kick that makes any T-spin a full one, as SRS does for T-spin triples and fins.
+    fn full_tspin_kick(&self) -> Option<usize> {
+        None
+    }
+
+    /// Which built in system this is, if it is one.
+    fn kind(&self) -> Option<RotationSystemKind> {
+        None
+    }
+}
+
+/// Each of the built in rotation systems, to pick one by name.
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub enum RotationSystemKind {
+    /// [`Srs`].
+    Srs,
+    /// [`Ars`].
+    Ars,
+    /// [`Nrs`].
+    Nrs,
+}
+
+impl RotationSystemKind {
+    /// The rotation system of this kind.
+    pub fn build(self) -> Arc<dyn RotationSystem> {
+        match self {
+            Self::Srs => Arc::new(Srs),
+            Self::Ars => Arc::new(Ars),
+            Self::Nrs => Arc::new(Nrs),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const ROTATIONS: [Rotation;4] = [Rotation::N, Rotation::E, Rotation::S, Rotation::W];
+
+    fn sorted(mut cells: [Offset;Piece::CELL_COUNT]) -> [Offset;Piece::CELL_COUNT] {
+        cells.sort_by_key(|cell| (cell.x, cell.y));
+        cells
+    }
+
+    #[test]
+    fn shapes_fit_their_box() {
+        let systems: [&dyn RotationSystem;3] = [&Srs, &Ars, &Nrs];
+        for system in systems {
+            for kind in Kind::ALL {
+                for rotation in ROTATIONS {
+                    let shape = system.shape(kind, rotation);
+                    assert!(
+                        shape.iter().all(|cell| (0..4).contains(&cell.x) && (0..4).contains(&cell.y)),
+                        "{:?} {:?} {:?} leaves its box: {:?}",
+                        system,
+                        kind,
+                        rotation,
+                        shape,
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn two_state_pieces_alternate() {
+        for system in [&Ars as &dyn RotationSystem, &Nrs] {
+            for kind in [Kind::I, Kind::S, Kind::Z] {
+                assert_eq!(
+                    sorted(system.shape(kind, Rotation::N)),
+                    sorted(system.shape(kind, Rotation::S)),
+                );
+                assert_eq!(
+                    sorted(system.shape(kind, Rotation::E)),
+                    sorted(system.shape(kind, Rotation::W)),
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn only_srs_upgrades_t_spins() {
+        assert_eq!(Srs.full_tspin_kick(), Some(4));
+        assert_eq!(Ars.full_tspin_kick(), None);
+        assert_eq!(Nrs.full_tspin_kick(), None);
+    }
+
+    #[test]
+    fn classic_systems_spawn_pointing_down() {
+        let pointing_down = [(0, 1), (1, 1), (2, 1), (1, 0)].map(Offset::from);
+        for system in [&Ars as &dyn RotationSystem, &Nrs] {
+            let rotation = system.spawn_rotation(Kind::T);
+            assert_eq!(sorted(system.shape(Kind::T, rotation)), sorted(pointing_down));
+        }
+    }
+}
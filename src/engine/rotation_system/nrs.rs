@@ -0,0 +1,31 @@
+use super::{RotationSystem, RotationSystemKind, Offset, Matrix, Piece, Kind, Rotation, srs};
+
+/// The right-handed Nintendo rotation system from the NES game.
+/// I, S and Z only have two orientations, and nothing ever kicks.
+#[derive(Clone, Copy, Debug)]
+pub struct Nrs;
+
+impl RotationSystem for Nrs {
+    fn shape(&self, kind: Kind, rotation: Rotation) -> [Offset;Piece::CELL_COUNT] {
+        match (kind, rotation) {
+            (Kind::I | Kind::S | Kind::Z, Rotation::N | Rotation::S) => srs::shape(kind, Rotation::S),
+            (Kind::I | Kind::S | Kind::Z, Rotation::E | Rotation::W) => srs::shape(kind, Rotation::E),
+            _ => srs::shape(kind, rotation),
+        }
+    }
+
+    fn spawn_rotation(&self, kind: Kind) -> Rotation {
+        match kind {
+            Kind::J | Kind::L | Kind::T => Rotation::S,
+            _ => Rotation::N,
+        }
+    }
+
+    fn kicks(&self, _matrix: &Matrix, _piece: &Piece, _to: Rotation) -> Vec<Offset> {
+        vec![Offset::new(0, 0)]
+    }
+
+    fn kind(&self) -> Option<RotationSystemKind> {
+        Some(RotationSystemKind::Nrs)
+    }
+}
@@ -0,0 +1,128 @@
+use super::{RotationSystem, RotationSystemKind, Offset, Matrix, Piece, Kind, Rotation};
+
+/// The Super Rotation System used by guideline games.
+#[derive(Clone, Copy, Debug)]
+pub struct Srs;
+
+impl RotationSystem for Srs {
+    fn shape(&self, kind: Kind, rotation: Rotation) -> [Offset;Piece::CELL_COUNT] {
+        shape(kind, rotation)
+    }
+
+    fn kicks(&self, _matrix: &Matrix, piece: &Piece, to: Rotation) -> Vec<Offset> {
+        kicks(piece.kind, piece.rotation, to).collect()
+    }
+
+    fn full_tspin_kick(&self) -> Option<usize> {
+        Some(4)
+    }
+
+    fn kind(&self) -> Option<RotationSystemKind> {
+        Some(RotationSystemKind::Srs)
+    }
+}
+
+/// Rotates about the centre of the piece's bounding box, so other systems
+/// can describe themselves in terms of this one.
+pub(super) fn shape(kind: Kind, rotation: Rotation) -> [Offset;Piece::CELL_COUNT] {
+    kind.cells().map(|cell| match kind {
+        Kind::O => cell,
+        _ => {
+            let grid_offset = rotation.intrinsic_offset() * (kind.grid_size() - 1);
+            cell * rotation + grid_offset
+        }
+    })
+}
+
+type KickTable = [[(isize, isize);5];4];
+
+// Rows are indexed by the starting rotation, in N, E, S, W order.
+const JLSTZ_CLOCKWISE: KickTable = [
+    /* N -> E */ [(0, 0), (-1, 0), (-1,  1), (0, -2), (-1, -2)],
+    /* E -> S */ [(0, 0), ( 1, 0), ( 1, -1), (0,  2), ( 1,  2)],
+    /* S -> W */ [(0, 0), ( 1, 0), ( 1,  1), (0, -2), ( 1, -2)],
+    /* W -> N */ [(0, 0), (-1, 0), (-1, -1), (0,  2), (-1,  2)],
+];
+
+const JLSTZ_COUNTER_CLOCKWISE: KickTable = [
+    /* N -> W */ [(0, 0), ( 1, 0), ( 1,  1), (0, -2), ( 1, -2)],
+    /* E -> N */ [(0, 0), ( 1, 0), ( 1, -1), (0,  2), ( 1,  2)],
+    /* S -> E */ [(0, 0), (-1, 0), (-1,  1), (0, -2), (-1, -2)],
+    /* W -> S */ [(0, 0), (-1, 0), (-1, -1), (0,  2), (-1,  2)],
+];
+
+const I_CLOCKWISE: KickTable = [
+    /* N -> E */ [(0, 0), (-2, 0), ( 1, 0), (-2, -1), ( 1,  2)],
+    /* E -> S */ [(0, 0), (-1, 0), ( 2, 0), (-1,  2), ( 2, -1)],
+    /* S -> W */ [(0, 0), ( 2, 0), (-1, 0), ( 2,  1), (-1, -2)],
+    /* W -> N */ [(0, 0), ( 1, 0), (-2, 0), ( 1, -2), (-2,  1)],
+];
+
+const I_COUNTER_CLOCKWISE: KickTable = [
+    /* N -> W */ [(0, 0), (-1, 0), ( 2, 0), (-1,  2), ( 2, -1)],
+    /* E -> N */ [(0, 0), ( 2, 0), (-1, 0), ( 2,  1), (-1, -2)],
+    /* S -> E */ [(0, 0), ( 1, 0), (-2, 0), ( 1, -2), (-2,  1)],
+    /* W -> S */ [(0, 0), (-2, 0), ( 1, 0), (-2, -1), ( 1,  2)],
+];
+
+const NO_KICKS: [(isize, isize);1] = [(0, 0)];
+
+pub(super) fn kicks(kind: Kind, from: Rotation, to: Rotation) -> impl Iterator<Item = Offset> {
+    debug_assert!(to != from, "Not a rotation: {:?} -> {:?}", from, to);
+
+    let clockwise = to == from.clockwise();
+    let half = to == from.clockwise().clockwise();
+    let tests: &'static [(isize, isize)] = match kind {
+        Kind::O => &NO_KICKS,
+        // SRS has no kicks for half turns, so they only happen in place
+        _ if half => &NO_KICKS,
+        Kind::I if clockwise => &I_CLOCKWISE[from as usize],
+        Kind::I => &I_COUNTER_CLOCKWISE[from as usize],
+        _ if clockwise => &JLSTZ_CLOCKWISE[from as usize],
+        _ => &JLSTZ_COUNTER_CLOCKWISE[from as usize],
+    };
+
+    tests.iter().copied().map(Offset::from)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const ROTATIONS: [Rotation;4] = [Rotation::N, Rotation::E, Rotation::S, Rotation::W];
+
+    #[test]
+    fn counter_clockwise_undoes_clockwise() {
+        for kind in [Kind::I, Kind::T] {
+            for from in ROTATIONS {
+                let to = from.clockwise();
+                let forward = kicks(kind, from, to).collect::<Vec<_>>();
+                let back = kicks(kind, to, from).collect::<Vec<_>>();
+                assert_eq!(forward.len(), back.len());
+                for (forward, back) in forward.into_iter().zip(back) {
+                    assert_eq!(forward, -back, "{:?} {:?} -> {:?}", kind, from, to);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn half_turns_dont_kick() {
+        for from in ROTATIONS {
+            assert_eq!(
+                kicks(Kind::T, from, from.clockwise().clockwise()).collect::<Vec<_>>(),
+                [Offset::new(0, 0)],
+            );
+        }
+    }
+
+    #[test]
+    fn o_never_kicks() {
+        for from in ROTATIONS {
+            assert_eq!(
+                kicks(Kind::O, from, from.clockwise()).collect::<Vec<_>>(),
+                [Offset::new(0, 0)],
+            );
+        }
+    }
+}
@@ -0,0 +1,57 @@
+use std::time::Duration;
+use serde::{Serialize, Deserialize};
+use super::{lock_delay::LockMode, stats::Goal, gravity::LineClearGravity};
+
+/// Tunable parts of the game that aren't tied to a rotation system or randomizer.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Rules {
+    /// How long a piece can rest on the stack before it locks.
+    pub lock_delay: Duration,
+    /// What resets the lock delay.
+    pub lock_mode: LockMode,
+    /// How soft drop speeds up the fall.
+    pub soft_drop: SoftDrop,
+    /// Lines it takes to level up.
+    pub goal: Goal,
+    /// How long full rows stay on the matrix before they're cleared.
+    pub line_clear_delay: Duration,
+    /// How the stack falls into cleared rows.
+    pub line_clear_gravity: LineClearGravity,
+    /// How long until the next piece spawns after a lock or line clear, also known as ARE.
+    pub entry_delay: Duration,
+    /// Whether pieces can be taken back.
+    pub mode: Mode,
+}
+
+/// Whether the game counts.
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub enum Mode {
+    /// No taking pieces back.
+    Ranked,
+    /// Placed pieces can be undone and redone, which is marked in the stats.
+    Practice,
+}
+
+/// How much faster pieces fall while soft drop is held.
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub enum SoftDrop {
+    /// Gravity is multiplied by this much, still capped at 20G.
+    Factor(u32),
+    /// The piece falls straight to the stack, but doesn't lock until the lock delay runs out.
+    Sonic,
+}
+
+impl Default for Rules {
+    fn default() -> Self {
+        Self {
+            lock_delay: Duration::from_millis(500),
+            lock_mode: LockMode::ExtendedPlacement,
+            soft_drop: SoftDrop::Factor(20),
+            goal: Goal::Fixed(10),
+            line_clear_delay: Duration::from_millis(300),
+            line_clear_gravity: LineClearGravity::Naive,
+            entry_delay: Duration::from_millis(100),
+            mode: Mode::Ranked,
+        }
+    }
+}
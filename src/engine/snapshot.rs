@@ -0,0 +1,289 @@
+use std::{collections::VecDeque, fmt, time::Duration};
+use serde::{Serialize, Deserialize};
+use super::{
+    Engine, Matrix,
+    piece::{Piece, Kind},
+    randomizer::RandomizerState,
+    rotation_system::RotationSystemKind,
+    lock_delay::LockDelay,
+    rules::Rules,
+    stats::Stats,
+    phase::Phase,
+};
+
+/// Everything about a game in progress, to save and carry on from later.
+/// Events still waiting to be drained and the undo history aren't included.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Snapshot {
+    matrix: Matrix,
+    randomizer: RandomizerState,
+    queue: VecDeque<Kind>,
+    rotation_system: RotationSystemKind,
+    rules: Rules,
+    cursor: Option<Piece>,
+    last_rotation: Option<usize>,
+    lock_delay: LockDelay,
+    gravity_progress: Duration,
+    soft_dropping: bool,
+    held: Option<Kind>,
+    hold_used: bool,
+    phase: Phase,
+    stats: Stats,
+}
+
+/// Why an engine couldn't be saved or restored.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum SnapshotError {
+    /// Only the built in randomizers can be saved.
+    CustomRandomizer,
+    /// Only the built in rotation systems can be saved.
+    CustomRotationSystem,
+    /// The matrix's cells don't match its size.
+    InvalidMatrix,
+    /// The cursor overlaps the stack or the walls.
+    InvalidCursor,
+    /// The phase doesn't fit the rest of the game, or clears rows that aren't there.
+    InvalidPhase,
+    /// The hold is marked as used with nothing in it.
+    InvalidHold,
+    /// The randomizer's state would break dealing.
+    InvalidRandomizer,
+    /// The stats are impossible, like being on level 0.
+    InvalidStats,
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SnapshotError::CustomRandomizer => write!(f, "Custom randomizers can't be saved"),
+            SnapshotError::CustomRotationSystem => write!(f, "Custom rotation systems can't be saved"),
+            SnapshotError::InvalidMatrix => write!(f, "Saved matrix is the wrong size"),
+            SnapshotError::InvalidCursor => write!(f, "Saved piece overlaps the stack"),
+            SnapshotError::InvalidPhase => write!(f, "Saved phase doesn't match the rest of the game"),
+            SnapshotError::InvalidHold => write!(f, "Saved hold is used but empty"),
+            SnapshotError::InvalidRandomizer => write!(f, "Saved randomizer is broken"),
+            SnapshotError::InvalidStats => write!(f, "Saved stats are impossible"),
+        }
+    }
+}
+
+impl Engine {
+    /// Saves the whole game, cursor, queue and timers included, in a form any serde format can store.
+    pub fn snapshot(&self) -> Result<Snapshot, SnapshotError> {
+        Ok(Snapshot {
+            matrix: self.matrix.clone(),
+            randomizer: self.randomizer.state().ok_or(SnapshotError::CustomRandomizer)?,
+            queue: self.queue.clone(),
+            rotation_system: self.rotation_system.kind().ok_or(SnapshotError::CustomRotationSystem)?,
+            rules: self.rules.clone(),
+            cursor: self.cursor,
+            last_rotation: self.last_rotation,
+            lock_delay: self.lock_delay,
+            gravity_progress: self.gravity_progress,
+            soft_dropping: self.soft_dropping,
+            held: self.held,
+            hold_used: self.hold_used,
+            phase: self.phase.clone(),
+            stats: self.stats.clone(),
+        })
+    }
+
+    /// Carries on from a snapshot, exactly as the engine it was taken from would have.
+    /// Snapshots that couldn't have come from a game, which would panic later on, are rejected.
+    pub fn from_snapshot(snapshot: Snapshot) -> Result<Self, SnapshotError> {
+        let matrix = snapshot.matrix;
+        if matrix.width < Matrix::MIN_WIDTH
+            || matrix.visible_height == 0
+            // Checked, so a hostile size can't overflow
+            || matrix.visible_height.checked_mul(2).and_then(|height| height.checked_mul(matrix.width))
+                != Some(matrix.cells.len())
+        {
+            return Err(SnapshotError::InvalidMatrix);
+        }
+
+        // Like the engine, this lets the cursor poke out above the buffer, as long as it doesn't clip
+        let rotation_system = snapshot.rotation_system.build();
+        if snapshot.cursor.is_some_and(|cursor| matrix.is_clipping(&cursor, &*rotation_system)) {
+            return Err(SnapshotError::InvalidCursor);
+        }
+
+        let phase_fits = match &snapshot.phase {
+            Phase::Falling => snapshot.cursor.is_some(),
+            // Worked out from the cursor when asked for, so never stored
+            Phase::Locking => false,
+            Phase::LineClearDelay { rows, .. } => snapshot.cursor.is_none()
+                && rows.windows(2).all(|pair| pair[0] < pair[1])
+                && rows.last().is_none_or(|&row| row < matrix.height()),
+            Phase::EntryDelay { .. } | Phase::ToppedOut(_) => snapshot.cursor.is_none(),
+        };
+        if !phase_fits {
+            return Err(SnapshotError::InvalidPhase);
+        }
+
+        if snapshot.hold_used && snapshot.held.is_none() {
+            return Err(SnapshotError::InvalidHold);
+        }
+        if !snapshot.randomizer.is_valid() {
+            return Err(SnapshotError::InvalidRandomizer);
+        }
+        if snapshot.stats.level == 0 {
+            return Err(SnapshotError::InvalidStats);
+        }
+
+        Ok(Self {
+            matrix,
+            randomizer: snapshot.randomizer.into_randomizer(),
+            queue: snapshot.queue,
+            rotation_system,
+            rules: snapshot.rules,
+            cursor: snapshot.cursor,
+            last_rotation: snapshot.last_rotation,
+            lock_delay: snapshot.lock_delay,
+            gravity_progress: snapshot.gravity_progress,
+            soft_dropping: snapshot.soft_dropping,
+            held: snapshot.held,
+            hold_used: snapshot.hold_used,
+            phase: snapshot.phase,
+            stats: snapshot.stats,
+            events: VecDeque::new(),
+            history: Default::default(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::engine::{MoveKind, RotateKind, randomizer::{Randomizer, RandomizerKind}, replay::Setup};
+
+    const FRAME: Duration = Duration::from_millis(16);
+
+    fn play(engine: &mut Engine, pieces: usize) {
+        for piece in 0..pieces {
+            engine.update(FRAME * 10).unwrap();
+            let _ = engine.rotate_cursor(RotateKind::Clockwise);
+            for _ in 0..piece % 4 {
+                let _ = engine.move_cursor(if piece % 2 == 0 { MoveKind::Left } else { MoveKind::Right });
+            }
+            if piece % 5 == 2 {
+                engine.hold().unwrap();
+            }
+            engine.update(FRAME).unwrap();
+            engine.hard_drop().unwrap();
+        }
+    }
+
+    #[test]
+    fn restored_engines_carry_on_the_same() {
+        let mut engine = crate::engine::replay::Setup::new(11).engine();
+        play(&mut engine, 6);
+        // Partway through a piece, with some of the queue already dealt
+        engine.update(FRAME * 10).unwrap();
+        engine.move_cursor(MoveKind::Left).unwrap();
+        engine.preview(5).for_each(drop);
+
+        let json = serde_json::to_string(&engine.snapshot().unwrap()).unwrap();
+        let mut restored = Engine::from_snapshot(serde_json::from_str(&json).unwrap()).unwrap();
+
+        play(&mut engine, 8);
+        play(&mut restored, 8);
+        assert_eq!(restored.matrix(), engine.matrix());
+        assert_eq!(restored.stats(), engine.stats());
+        assert_eq!(restored.held_piece(), engine.held_piece());
+        assert!(restored.preview(7).eq(engine.preview(7)));
+    }
+
+    #[test]
+    fn custom_parts_cant_be_saved() {
+        #[derive(Clone, Debug)]
+        struct OnlyI;
+
+        impl Randomizer for OnlyI {
+            fn next(&mut self) -> Kind {
+                Kind::I
+            }
+        }
+
+        let engine = Engine::with_randomizer(OnlyI);
+        assert_eq!(engine.snapshot().unwrap_err(), SnapshotError::CustomRandomizer);
+    }
+
+    #[test]
+    fn mismatched_matrix_is_rejected() {
+        let mut snapshot = Engine::new().snapshot().unwrap();
+        snapshot.matrix.cells.pop();
+        assert!(matches!(Engine::from_snapshot(snapshot), Err(SnapshotError::InvalidMatrix)));
+    }
+
+    fn rejection(corrupt: impl FnOnce(&mut Snapshot)) -> SnapshotError {
+        let mut engine = Engine::new();
+        engine.update(FRAME * 10).unwrap();
+        let mut snapshot = engine.snapshot().unwrap();
+        corrupt(&mut snapshot);
+        Engine::from_snapshot(snapshot).err().expect("corrupted snapshot was accepted")
+    }
+
+    #[test]
+    fn corrupted_snapshots_are_rejected() {
+        let error = rejection(|snapshot| snapshot.cursor.as_mut().unwrap().position.x = -5);
+        assert_eq!(error, SnapshotError::InvalidCursor);
+
+        let error = rejection(|snapshot| snapshot.matrix.cells.fill(Some(crate::engine::Color::Red)));
+        assert_eq!(error, SnapshotError::InvalidCursor);
+
+        let error = rejection(|snapshot| snapshot.phase = Phase::EntryDelay { elapsed: Duration::ZERO });
+        assert_eq!(error, SnapshotError::InvalidPhase);
+
+        let error = rejection(|snapshot| {
+            snapshot.cursor = None;
+            snapshot.phase = Phase::LineClearDelay { rows: vec![3, 400], elapsed: Duration::ZERO, chain: 0 };
+        });
+        assert_eq!(error, SnapshotError::InvalidPhase);
+
+        let error = rejection(|snapshot| {
+            snapshot.cursor = None;
+            snapshot.phase = Phase::LineClearDelay { rows: vec![2, 2], elapsed: Duration::ZERO, chain: 0 };
+        });
+        assert_eq!(error, SnapshotError::InvalidPhase);
+
+        let error = rejection(|snapshot| snapshot.hold_used = true);
+        assert_eq!(error, SnapshotError::InvalidHold);
+
+        let error = rejection(|snapshot| snapshot.stats.level = 0);
+        assert_eq!(error, SnapshotError::InvalidStats);
+
+        let error = rejection(|snapshot| {
+            snapshot.matrix.width = usize::MAX / 4;
+            snapshot.matrix.visible_height = 8;
+        });
+        assert_eq!(error, SnapshotError::InvalidMatrix);
+    }
+
+    /// Corrupts a snapshot through its JSON, to get at the randomizer's insides.
+    fn randomizer_rejection(kind: RandomizerKind, corrupt: impl FnOnce(&mut serde_json::Value)) -> SnapshotError {
+        let mut engine = Setup { randomizer: kind, ..Setup::new(4) }.engine();
+        engine.update(FRAME * 10).unwrap();
+        let mut json = serde_json::to_value(engine.snapshot().unwrap()).unwrap();
+        corrupt(&mut json["randomizer"]);
+        let snapshot = serde_json::from_value(json).unwrap();
+        Engine::from_snapshot(snapshot).err().expect("corrupted randomizer was accepted")
+    }
+
+    #[test]
+    fn broken_randomizers_are_rejected() {
+        let error = randomizer_rejection(RandomizerKind::Bag7, |state| {
+            state["Bag"]["copies"] = 0.into();
+            state["Bag"]["bag"] = serde_json::json!([]);
+        });
+        assert_eq!(error, SnapshotError::InvalidRandomizer);
+
+        let error = randomizer_rejection(RandomizerKind::Tgm3, |state| state["Tgm3"]["pool"] = serde_json::json!([]));
+        assert_eq!(error, SnapshotError::InvalidRandomizer);
+
+        let error = randomizer_rejection(RandomizerKind::Tgm3, |state| state["Tgm3"]["droughts"] = serde_json::json!([]));
+        assert_eq!(error, SnapshotError::InvalidRandomizer);
+
+        let error = randomizer_rejection(RandomizerKind::Tgm, |state| state["History"]["rolls"] = 0.into());
+        assert_eq!(error, SnapshotError::InvalidRandomizer);
+    }
+}
@@ -0,0 +1,305 @@
+use serde::{Serialize, Deserialize};
+
+/// How a T piece got into the spot it locked in.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+pub enum TSpin {
+    /// Not a T-spin.
+    #[default]
+    None,
+    /// Only one of the corners the T points towards is filled.
+    Mini,
+    /// Both corners the T points towards are filled, or it got there with the last kick.
+    Full,
+}
+
+/// How many lines it takes to get to the next level.
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub enum Goal {
+    /// The same number of lines every level.
+    Fixed(u32),
+    /// Five times the level, counted in awarded lines: harder clears count for more.
+    Variable,
+}
+
+/// Progress through a game, following the scoring guideline.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Stats {
+    /// Points so far.
+    pub score: u64,
+    /// The current level, starting from 1.
+    pub level: u32,
+    /// Lines cleared so far.
+    pub lines: u32,
+    /// Lines counted towards the current level's goal.
+    pub goal_progress: u32,
+    /// How many line clears in a row, after the first. `None` if the last piece didn't clear anything.
+    pub combo: Option<u32>,
+    /// Whether the last line clear was a tetris or T-spin, so the next one can earn a bonus.
+    pub back_to_back: bool,
+    /// How many pieces were taken back in practice mode.
+    pub undos: u32,
+    /// Whether the game was played in practice mode, so doesn't count, undos or not.
+    pub practice: bool,
+}
+
+impl Stats {
+    /// Whether these are from a real game, not a practice one.
+    pub fn is_legit(&self) -> bool {
+        !self.practice && self.undos == 0
+    }
+}
+
+/// What a single piece earned when it locked.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct Award {
+    /// Points scored, level multiplier included.
+    pub points: u64,
+    /// The combo this continued, if it cleared anything.
+    pub combo: Option<u32>,
+    /// Whether the back to back bonus applied.
+    pub back_to_back: bool,
+    /// Whether it emptied the matrix.
+    pub perfect_clear: bool,
+    /// Whether it took the game to the next level.
+    pub level_up: bool,
+}
+
+impl Stats {
+    /// A fresh game starting at `level`, or 1 if that's lower.
+    pub fn new(level: u32) -> Self {
+        Self {
+            score: 0,
+            level: level.max(1),
+            lines: 0,
+            goal_progress: 0,
+            combo: None,
+            back_to_back: false,
+            undos: 0,
+            practice: false,
+        }
+    }
+
+    /// Lines needed to clear the current level.
+    pub fn goal(&self, goal: Goal) -> u32 {
+        match goal {
+            Goal::Fixed(lines) => lines,
+            Goal::Variable => self.level * 5,
+        }
+    }
+
+    /// One point per cell soft dropped.
+    pub fn award_soft_drop(&mut self, cells: u32) {
+        self.score += cells as u64;
+    }
+
+    /// Two points per cell hard dropped.
+    pub fn award_hard_drop(&mut self, cells: u32) {
+        self.score += 2 * cells as u64;
+    }
+
+    /// Scores a piece locking and clearing `lines` lines.
+    pub fn award_lock(&mut self, lines: usize, tspin: TSpin, perfect_clear: bool, goal: Goal) -> Award {
+        let level = self.level as u64;
+        let difficult = lines == 4 || (lines > 0 && tspin != TSpin::None);
+        let back_to_back = difficult && self.back_to_back;
+
+        let mut action = action_points(lines, tspin);
+        if back_to_back {
+            action += action / 2;
+        }
+
+        if lines > 0 {
+            self.combo = Some(self.combo.map_or(0, |combo| combo + 1));
+            self.back_to_back = difficult;
+        } else {
+            self.combo = None;
+        }
+        let combo = self.combo.unwrap_or(0) as u64 * 50;
+
+        let perfect_clear_bonus = match (perfect_clear, lines) {
+            (false, _) | (true, 0) => 0,
+            (true, 4) if back_to_back => 3200,
+            (true, 1) => 800,
+            (true, 2) => 1200,
+            (true, 3) => 1800,
+            (true, _) => 2000,
+        };
+
+        let points = (action + combo + perfect_clear_bonus) * level;
+        self.score += points;
+
+        self.lines += lines as u32;
+        self.goal_progress += match goal {
+            Goal::Fixed(_) => lines as u32,
+            Goal::Variable => {
+                let awarded = awarded_lines(lines, tspin);
+                if back_to_back { awarded + awarded / 2 } else { awarded }
+            }
+        };
+
+        let level_up = self.goal_progress >= self.goal(goal);
+        if level_up {
+            self.goal_progress -= self.goal(goal);
+            self.level += 1;
+        }
+
+        Award {
+            points,
+            combo: self.combo,
+            back_to_back,
+            perfect_clear: perfect_clear && lines > 0,
+            level_up,
+        }
+    }
+}
+
+impl Default for Stats {
+    fn default() -> Self {
+        Self::new(1)
+    }
+}
+
+/// Points for a clear at level 1, before any bonuses.
+fn action_points(lines: usize, tspin: TSpin) -> u64 {
+    match (tspin, lines) {
+        (TSpin::None, 0) => 0,
+        (TSpin::None, 1) => 100,
+        (TSpin::None, 2) => 300,
+        (TSpin::None, 3) => 500,
+        (TSpin::None, _) => 800,
+        (TSpin::Mini, 0) => 100,
+        (TSpin::Mini, 1) => 200,
+        (TSpin::Mini, _) => 400,
+        (TSpin::Full, 0) => 400,
+        (TSpin::Full, 1) => 800,
+        (TSpin::Full, 2) => 1200,
+        (TSpin::Full, _) => 1600,
+    }
+}
+
+/// Lines awarded towards a variable goal.
+fn awarded_lines(lines: usize, tspin: TSpin) -> u32 {
+    action_points(lines, tspin) as u32 / 100
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const GOAL: Goal = Goal::Fixed(10);
+
+    #[test]
+    fn guideline_table() {
+        let table = [
+            (1, TSpin::None, 100),
+            (2, TSpin::None, 300),
+            (3, TSpin::None, 500),
+            (4, TSpin::None, 800),
+            (0, TSpin::Mini, 100),
+            (1, TSpin::Mini, 200),
+            (2, TSpin::Mini, 400),
+            (0, TSpin::Full, 400),
+            (1, TSpin::Full, 800),
+            (2, TSpin::Full, 1200),
+            (3, TSpin::Full, 1600),
+        ];
+
+        for level in [1, 4] {
+            for (lines, tspin, points) in table {
+                let mut stats = Stats::new(level);
+                let award = stats.award_lock(lines, tspin, false, GOAL);
+                assert_eq!(award.points, points * level as u64, "{} lines, {:?}", lines, tspin);
+                assert_eq!(stats.score, award.points);
+            }
+        }
+    }
+
+    #[test]
+    fn back_to_back_tetrises() {
+        let mut stats = Stats::new(1);
+        assert_eq!(stats.award_lock(4, TSpin::None, false, GOAL).points, 800);
+
+        let award = stats.award_lock(4, TSpin::None, false, GOAL);
+        assert!(award.back_to_back);
+        assert_eq!(award.points, 1200 + 50);
+    }
+
+    #[test]
+    fn t_spin_without_lines_keeps_back_to_back() {
+        let mut stats = Stats::new(1);
+        stats.award_lock(2, TSpin::Full, false, GOAL);
+        stats.award_lock(0, TSpin::Full, false, GOAL);
+
+        let award = stats.award_lock(1, TSpin::Full, false, GOAL);
+        assert!(award.back_to_back);
+        assert_eq!(award.points, 1200);
+    }
+
+    #[test]
+    fn easy_clear_breaks_back_to_back() {
+        let mut stats = Stats::new(1);
+        stats.award_lock(4, TSpin::None, false, GOAL);
+        stats.award_lock(1, TSpin::None, false, GOAL);
+
+        let award = stats.award_lock(4, TSpin::None, false, GOAL);
+        assert!(!award.back_to_back);
+    }
+
+    #[test]
+    fn combos_build_and_break() {
+        let mut stats = Stats::new(2);
+        let points = [1, 1, 1].map(|lines| stats.award_lock(lines, TSpin::None, false, GOAL).points);
+        assert_eq!(points, [200, 200 + 100, 200 + 200]);
+        assert_eq!(stats.combo, Some(2));
+
+        stats.award_lock(0, TSpin::None, false, GOAL);
+        assert_eq!(stats.combo, None);
+        assert_eq!(stats.award_lock(1, TSpin::None, false, GOAL).points, 200);
+    }
+
+    #[test]
+    fn perfect_clears() {
+        let mut stats = Stats::new(1);
+        let award = stats.award_lock(2, TSpin::None, true, GOAL);
+        assert!(award.perfect_clear);
+        assert_eq!(award.points, 300 + 1200);
+
+        let mut stats = Stats::new(1);
+        stats.award_lock(4, TSpin::None, false, GOAL);
+        let award = stats.award_lock(4, TSpin::None, true, GOAL);
+        assert_eq!(award.points, 1200 + 50 + 3200);
+    }
+
+    #[test]
+    fn drops() {
+        let mut stats = Stats::new(5);
+        stats.award_soft_drop(3);
+        stats.award_hard_drop(10);
+        assert_eq!(stats.score, 23);
+    }
+
+    #[test]
+    fn fixed_goal_levels() {
+        let mut stats = Stats::new(1);
+        for _ in 0..2 {
+            assert!(!stats.award_lock(4, TSpin::None, false, GOAL).level_up);
+        }
+        assert!(stats.award_lock(3, TSpin::None, false, GOAL).level_up);
+        assert_eq!(stats.level, 2);
+        assert_eq!(stats.lines, 11);
+        assert_eq!(stats.goal_progress, 1);
+    }
+
+    #[test]
+    fn variable_goal_levels() {
+        let mut stats = Stats::new(1);
+        // A tetris is worth eight lines against a goal of five
+        assert!(stats.award_lock(4, TSpin::None, false, Goal::Variable).level_up);
+        assert_eq!(stats.goal_progress, 3);
+        assert_eq!(stats.goal(Goal::Variable), 10);
+
+        // A back to back T-spin double is worth 12 + 6
+        stats.award_lock(2, TSpin::Full, false, Goal::Variable);
+        assert_eq!(stats.level, 3);
+    }
+}
@@ -0,0 +1,234 @@
+use std::{fmt, sync::Arc};
+use super::{Engine, rules::Mode};
+
+/// Earlier and later states to go back and forth between in practice mode.
+/// Each one holds its own history, so they form a chain that's cheap to clone.
+#[derive(Clone, Default)]
+pub(super) struct History {
+    /// The engine as the current piece spawned, which becomes the undo point once it's placed.
+    piece_start: Option<Arc<Engine>>,
+    /// The engine as the last placed piece spawned.
+    undo: Option<Arc<Engine>>,
+    /// The engine as it was before the last undo.
+    redo: Option<Arc<Engine>>,
+}
+
+/// Why there was nothing to undo or redo.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum UndoError {
+    /// Only practice games can be undone.
+    NotPractice,
+    /// No piece has been placed, or undone, to go back to.
+    NoHistory,
+}
+
+impl fmt::Display for UndoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UndoError::NotPractice => write!(f, "Only practice games can be undone"),
+            UndoError::NoHistory => write!(f, "Nothing to go back to"),
+        }
+    }
+}
+
+impl Engine {
+    /// Called as each piece spawns, the first marking the game as practice if it is one.
+    pub(super) fn on_piece_start(&mut self) {
+        if self.rules.mode != Mode::Practice {
+            return;
+        }
+        self.stats.practice = true;
+        let mut start = self.clone();
+        start.events.clear();
+        self.history.piece_start = Some(Arc::new(start));
+    }
+
+    /// Called as the cursor locks, or the game tops out, so undo brings back the piece that was in play.
+    pub(super) fn on_piece_end(&mut self) {
+        if let Some(start) = self.history.piece_start.take() {
+            self.history.undo = Some(start);
+            self.history.redo = None;
+        }
+    }
+
+    /// Takes back the last piece placed, putting it back at the top of the matrix.
+    /// Only allowed in practice mode, and counted in the stats.
+    pub fn undo(&mut self) -> Result<(), UndoError> {
+        if self.rules.mode != Mode::Practice {
+            return Err(UndoError::NotPractice);
+        }
+        let target = self.history.undo.clone().ok_or(UndoError::NoHistory)?;
+
+        let mut current = self.clone();
+        current.events.clear();
+        let undos = self.stats.undos + 1;
+        self.restore(&target, undos);
+        self.history.piece_start = Some(target);
+        self.history.redo = Some(Arc::new(current));
+        Ok(())
+    }
+
+    /// Goes back to how things were before the last undo, if no piece has been placed since.
+    pub fn redo(&mut self) -> Result<(), UndoError> {
+        let target = self.history.redo.clone().ok_or(UndoError::NoHistory)?;
+        self.restore(&target, self.stats.undos);
+        Ok(())
+    }
+
+    /// Whether [`Engine::undo`] would do anything.
+    pub fn can_undo(&self) -> bool {
+        self.rules.mode == Mode::Practice && self.history.undo.is_some()
+    }
+
+    /// Whether [`Engine::redo`] would do anything.
+    pub fn can_redo(&self) -> bool {
+        self.history.redo.is_some()
+    }
+
+    /// Swaps in an earlier or later state, keeping what isn't part of the game itself:
+    /// held inputs, events yet to be drained, and how many undos it's taken to get here.
+    fn restore(&mut self, target: &Engine, undos: u32) {
+        let soft_dropping = self.soft_dropping;
+        let events = std::mem::take(&mut self.events);
+        *self = target.clone();
+        self.soft_dropping = soft_dropping;
+        self.events = events;
+        self.stats.undos = undos;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+    use super::*;
+    use crate::engine::{MoveKind, Phase, TopOut, rules::Rules, replay::Setup};
+
+    fn practice() -> Engine {
+        let mut engine = Setup {
+            rules: Rules { mode: Mode::Practice, ..Rules::default() },
+            ..Setup::new(3)
+        }.engine();
+        engine.update(Duration::from_millis(100)).unwrap();
+        engine
+    }
+
+    /// Hard drops the cursor after moving it left `moves` times, and waits for the next piece.
+    fn place(engine: &mut Engine, moves: usize) {
+        for _ in 0..moves {
+            engine.move_cursor(MoveKind::Left).unwrap();
+        }
+        engine.hard_drop().unwrap();
+        engine.update(Duration::from_millis(400)).unwrap();
+    }
+
+    #[test]
+    fn undo_takes_back_the_last_piece() {
+        let mut engine = practice();
+        place(&mut engine, 0);
+        let before = (engine.matrix().clone(), engine.cursor(), engine.stats().clone());
+
+        place(&mut engine, 3);
+        let after = (engine.matrix().clone(), engine.cursor());
+
+        engine.undo().unwrap();
+        assert_eq!(engine.matrix(), &before.0);
+        assert_eq!(engine.cursor(), before.1);
+        assert_eq!(engine.stats().undos, 1);
+        assert_eq!(engine.phase(), Phase::Falling);
+
+        engine.redo().unwrap();
+        assert_eq!((engine.matrix().clone(), engine.cursor()), after);
+        assert!(!engine.stats().is_legit());
+        assert!(!engine.can_redo());
+    }
+
+    #[test]
+    fn undo_goes_back_a_piece_at_a_time() {
+        let mut engine = practice();
+        let start = engine.cursor();
+        for moves in [0, 1, 2] {
+            place(&mut engine, moves);
+        }
+
+        engine.undo().unwrap();
+        engine.undo().unwrap();
+        engine.undo().unwrap();
+        assert_eq!(engine.cursor(), start);
+        assert!(engine.matrix().lines().flatten().all(Option::is_none));
+        assert_eq!(engine.undo(), Err(UndoError::NoHistory));
+
+        engine.redo().unwrap();
+        engine.redo().unwrap();
+        assert!(engine.can_redo());
+
+        // Placing a piece starts a new timeline
+        place(&mut engine, 3);
+        assert!(!engine.can_redo());
+        assert_eq!(engine.stats().undos, 3);
+    }
+
+    #[test]
+    fn undo_brings_back_a_game_that_topped_out() {
+        let mut engine = practice();
+        let top_out = loop {
+            engine.hard_drop().unwrap();
+            if let Err(top_out) = engine.update(Duration::from_millis(400)) {
+                break top_out;
+            }
+        };
+        assert_eq!(engine.phase(), Phase::ToppedOut(top_out));
+
+        // Back to the last piece placed, sitting right on top of the stack
+        engine.undo().unwrap();
+        assert_eq!(engine.phase(), Phase::Locking);
+        assert!(engine.cursor().is_some());
+    }
+
+    #[test]
+    fn undo_after_holding_into_a_block_out() {
+        let mut engine = practice();
+        place(&mut engine, 0);
+        let start = engine.history.piece_start.as_ref().unwrap().cursor();
+
+        // Fill in where the next piece spawns, around the cursor
+        let cursor = engine.cursor().unwrap().cells(engine.rotation_system()).unwrap();
+        let visible_height = engine.matrix().visible_height();
+        for y in visible_height..visible_height + 2 {
+            for x in 0..engine.matrix().width() {
+                let coord = crate::engine::Coordinate::new(x, y);
+                if !cursor.contains(&coord) {
+                    engine.matrix[coord] = Some(crate::engine::Color::Red);
+                }
+            }
+        }
+        assert_eq!(engine.hold(), Err(TopOut::BlockOut));
+
+        // Back to the piece that was held, not the one before it
+        engine.undo().unwrap();
+        assert_eq!(engine.cursor(), start);
+        assert_eq!(engine.held_piece(), None);
+    }
+
+    #[test]
+    fn practice_games_never_count() {
+        let mut engine = practice();
+        place(&mut engine, 0);
+        place(&mut engine, 1);
+
+        assert_eq!(engine.stats().undos, 0);
+        assert!(engine.stats().practice);
+        assert!(!engine.stats().is_legit());
+    }
+
+    #[test]
+    fn ranked_games_cant_undo() {
+        let mut engine = Setup::new(3).engine();
+        engine.update(Duration::from_millis(100)).unwrap();
+        place(&mut engine, 0);
+        place(&mut engine, 0);
+
+        assert!(!engine.can_undo());
+        assert_eq!(engine.undo(), Err(UndoError::NotPractice));
+        assert!(engine.stats().is_legit());
+    }
+}
@@ -0,0 +1,257 @@
+use std::time::Duration;
+
+use tehtrys::engine::MoveKind;
+
+/// How a held direction turns into movement, to the player's taste.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Handling {
+    /// Delayed auto shift: how long a direction is held before it starts repeating.
+    pub das: Duration,
+    /// Auto repeat rate: the time between repeats. Zero goes straight to the wall.
+    pub arr: Duration,
+    /// DAS cut delay: repeating pauses this long after a rotation, hold or new piece.
+    pub dcd: Duration,
+}
+
+impl Default for Handling {
+    fn default() -> Self {
+        Self {
+            das: Duration::from_millis(167),
+            arr: Duration::from_millis(33),
+            dcd: Duration::ZERO,
+        }
+    }
+}
+
+/// Movement due from the held direction.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Shift {
+    By(MoveKind, u32),
+    ToWall(MoveKind),
+}
+
+/// Tracks which directions are held and when, turning them into shifts.
+/// Times are measured from any fixed starting point, as long as they never go backwards.
+#[derive(Clone, Debug)]
+pub struct AutoShift {
+    handling: Handling,
+    left: bool,
+    right: bool,
+    active: Option<Active>,
+    /// A tap let go of before an update got to deliver it.
+    pending_tap: Option<MoveKind>,
+}
+
+/// The direction currently in control, which is the one pressed most recently.
+#[derive(Clone, Copy, Debug)]
+struct Active {
+    direction: MoveKind,
+    tapped: bool,
+    repeat_start: Duration,
+    repeats: u32,
+}
+
+impl Active {
+    fn new(direction: MoveKind, tapped: bool, now: Duration, das: Duration) -> Self {
+        Self {
+            direction,
+            tapped,
+            repeat_start: now + das,
+            repeats: 0,
+        }
+    }
+}
+
+impl AutoShift {
+    pub fn new(handling: Handling) -> Self {
+        Self {
+            handling,
+            left: false,
+            right: false,
+            active: None,
+            pending_tap: None,
+        }
+    }
+
+    pub fn press(&mut self, direction: MoveKind, now: Duration) {
+        *self.held_mut(direction) = true;
+        self.active = Some(Active::new(direction, false, now, self.handling.das));
+    }
+
+    /// Lets go of `direction`. If the other one is still held it takes back over,
+    /// charging up again from now without another tap.
+    pub fn release(&mut self, direction: MoveKind, now: Duration) {
+        *self.held_mut(direction) = false;
+        let Some(active) = self.active.filter(|active| active.direction == direction) else {
+            return;
+        };
+        if !active.tapped {
+            self.pending_tap = Some(direction);
+        }
+
+        let other = match direction {
+            MoveKind::Left => MoveKind::Right,
+            MoveKind::Right => MoveKind::Left,
+        };
+        self.active = self.held(other)
+            .then(|| Active::new(other, true, now, self.handling.das));
+    }
+
+    /// Holds off repeating for the DAS cut delay, keeping any charge already built up.
+    pub fn cut(&mut self, now: Duration) {
+        if self.handling.dcd.is_zero() {
+            return;
+        }
+        if let Some(active) = &mut self.active {
+            active.repeat_start = active.repeat_start.max(now + self.handling.dcd);
+            active.repeats = 0;
+        }
+    }
+
+    /// Shifts due since the last update.
+    pub fn update(&mut self, now: Duration) -> Option<Shift> {
+        if let Some(direction) = self.pending_tap.take() {
+            return Some(Shift::By(direction, 1));
+        }
+
+        let arr = self.handling.arr;
+        let active = self.active.as_mut()?;
+
+        let tap = !active.tapped;
+        active.tapped = true;
+
+        if now < active.repeat_start {
+            return tap.then_some(Shift::By(active.direction, 1));
+        }
+        if arr.is_zero() {
+            return Some(Shift::ToWall(active.direction));
+        }
+
+        let due = ((now - active.repeat_start).as_nanos() / arr.as_nanos()) as u32 + 1;
+        let moves = due - active.repeats + tap as u32;
+        active.repeats = due;
+
+        (moves > 0).then_some(Shift::By(active.direction, moves))
+    }
+
+    fn held(&self, direction: MoveKind) -> bool {
+        match direction {
+            MoveKind::Left => self.left,
+            MoveKind::Right => self.right,
+        }
+    }
+
+    fn held_mut(&mut self, direction: MoveKind) -> &mut bool {
+        match direction {
+            MoveKind::Left => &mut self.left,
+            MoveKind::Right => &mut self.right,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    fn auto_shift(das: u64, arr: u64, dcd: u64) -> AutoShift {
+        AutoShift::new(Handling { das: ms(das), arr: ms(arr), dcd: ms(dcd) })
+    }
+
+    #[test]
+    fn tap_then_charge_then_repeat() {
+        let mut shift = auto_shift(100, 20, 0);
+        shift.press(MoveKind::Right, ms(0));
+
+        assert_eq!(shift.update(ms(0)), Some(Shift::By(MoveKind::Right, 1)));
+        assert_eq!(shift.update(ms(50)), None);
+        assert_eq!(shift.update(ms(99)), None);
+        assert_eq!(shift.update(ms(100)), Some(Shift::By(MoveKind::Right, 1)));
+        assert_eq!(shift.update(ms(110)), None);
+        assert_eq!(shift.update(ms(160)), Some(Shift::By(MoveKind::Right, 3)));
+
+        shift.release(MoveKind::Right, ms(170));
+        assert_eq!(shift.update(ms(500)), None);
+    }
+
+    #[test]
+    fn quick_tap_still_moves() {
+        let mut shift = auto_shift(100, 20, 0);
+        shift.press(MoveKind::Left, ms(0));
+        shift.release(MoveKind::Left, ms(0));
+
+        assert_eq!(shift.update(ms(0)), Some(Shift::By(MoveKind::Left, 1)));
+        assert_eq!(shift.update(ms(16)), None);
+        assert_eq!(shift.update(ms(500)), None);
+    }
+
+    #[test]
+    fn late_update_catches_up() {
+        let mut shift = auto_shift(100, 20, 0);
+        shift.press(MoveKind::Left, ms(0));
+        assert_eq!(shift.update(ms(140)), Some(Shift::By(MoveKind::Left, 4)));
+    }
+
+    #[test]
+    fn zero_arr_goes_to_the_wall() {
+        let mut shift = auto_shift(100, 0, 0);
+        shift.press(MoveKind::Left, ms(0));
+
+        assert_eq!(shift.update(ms(0)), Some(Shift::By(MoveKind::Left, 1)));
+        assert_eq!(shift.update(ms(100)), Some(Shift::ToWall(MoveKind::Left)));
+        assert_eq!(shift.update(ms(117)), Some(Shift::ToWall(MoveKind::Left)));
+    }
+
+    #[test]
+    fn last_pressed_direction_wins() {
+        let mut shift = auto_shift(100, 20, 0);
+        shift.press(MoveKind::Left, ms(0));
+        shift.update(ms(0));
+
+        shift.press(MoveKind::Right, ms(150));
+        assert_eq!(shift.update(ms(150)), Some(Shift::By(MoveKind::Right, 1)));
+        assert_eq!(shift.update(ms(200)), None);
+
+        // Letting go of right hands back to left, which charges up again
+        shift.release(MoveKind::Right, ms(200));
+        assert_eq!(shift.update(ms(250)), None);
+        assert_eq!(shift.update(ms(300)), Some(Shift::By(MoveKind::Left, 1)));
+    }
+
+    #[test]
+    fn releasing_the_inactive_direction_changes_nothing() {
+        let mut shift = auto_shift(100, 20, 0);
+        shift.press(MoveKind::Left, ms(0));
+        shift.press(MoveKind::Right, ms(10));
+        shift.update(ms(10));
+
+        shift.release(MoveKind::Left, ms(50));
+        assert_eq!(shift.update(ms(110)), Some(Shift::By(MoveKind::Right, 1)));
+    }
+
+    #[test]
+    fn cut_delays_repeats() {
+        let mut shift = auto_shift(100, 20, 50);
+        shift.press(MoveKind::Right, ms(0));
+        assert_eq!(shift.update(ms(120)), Some(Shift::By(MoveKind::Right, 3)));
+
+        shift.cut(ms(120));
+        assert_eq!(shift.update(ms(169)), None);
+        assert_eq!(shift.update(ms(170)), Some(Shift::By(MoveKind::Right, 1)));
+        assert_eq!(shift.update(ms(190)), Some(Shift::By(MoveKind::Right, 1)));
+    }
+
+    #[test]
+    fn cut_while_charging_waits_for_the_longer_of_the_two() {
+        let mut shift = auto_shift(100, 20, 50);
+        shift.press(MoveKind::Right, ms(0));
+        shift.update(ms(0));
+
+        shift.cut(ms(10));
+        assert_eq!(shift.update(ms(99)), None);
+        assert_eq!(shift.update(ms(100)), Some(Shift::By(MoveKind::Right, 1)));
+    }
+}
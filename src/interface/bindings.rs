@@ -0,0 +1,365 @@
+use std::{collections::HashMap, fmt, fs, hash::Hash, io, path::PathBuf, time::Duration};
+
+use sdl2::{keyboard::Keycode, controller::{Axis, Button}};
+use toml::{Value, value::Table};
+
+use tehtrys::engine::{MoveKind, RotateKind};
+use super::{controller::{PadControl, Direction}, auto_shift::Handling};
+
+/// Something the player can do with a key.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Input {
+    Move(MoveKind),
+    Rotate(RotateKind),
+    Hold,
+    SoftDrop,
+    HardDrop,
+    Pause,
+    Restart,
+    /// Only does anything in practice mode.
+    Undo,
+    Redo,
+}
+
+impl Input {
+    /// Every action, by the name it goes by in the config file.
+    const NAMED: [(&'static str, Input);12] = [
+        ("move_left", Input::Move(MoveKind::Left)),
+        ("move_right", Input::Move(MoveKind::Right)),
+        ("rotate_clockwise", Input::Rotate(RotateKind::Clockwise)),
+        ("rotate_counter_clockwise", Input::Rotate(RotateKind::CounterClockwise)),
+        ("rotate_180", Input::Rotate(RotateKind::Half)),
+        ("hold", Input::Hold),
+        ("soft_drop", Input::SoftDrop),
+        ("hard_drop", Input::HardDrop),
+        ("pause", Input::Pause),
+        ("restart", Input::Restart),
+        ("undo", Input::Undo),
+        ("redo", Input::Redo),
+    ];
+
+    fn from_name(name: &str) -> Option<Self> {
+        Self::NAMED.iter()
+            .find(|(named, _)| *named == name)
+            .map(|&(_, input)| input)
+    }
+
+    fn name(self) -> &'static str {
+        Self::NAMED.iter()
+            .find(|(_, input)| *input == self)
+            .map(|(name, _)| *name)
+            .unwrap()
+    }
+}
+
+/// Which keys and controller inputs trigger which actions.
+/// Any number of them can share an action, but not the other way round.
+#[derive(Clone, Debug)]
+pub struct Bindings {
+    keys: HashMap<Keycode, Input>,
+    pad: HashMap<PadControl, Input>,
+    /// How far an analog stick or trigger has to move before it counts, out of `i16::MAX`.
+    pub deadzone: i16,
+    /// How held directions repeat, which goes along with the keys they're held on.
+    pub handling: Handling,
+}
+
+impl Bindings {
+    const FILE_NAME: &'static str = "bindings.toml";
+
+    pub fn key(&self, key: Keycode) -> Option<Input> {
+        self.keys.get(&key).copied()
+    }
+
+    pub fn pad(&self, control: PadControl) -> Option<Input> {
+        self.pad.get(&control).copied()
+    }
+
+    /// Where the bindings are read from, in the user's config directory.
+    pub fn path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("tehtrys").join(Self::FILE_NAME))
+    }
+
+    /// Reads the bindings file, falling back to the defaults if there isn't one.
+    pub fn load() -> Result<Self, ConfigError> {
+        let Some(path) = Self::path() else {
+            return Ok(Self::default());
+        };
+
+        match fs::read_to_string(&path) {
+            Ok(text) => Self::parse(&text),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(error) => Err(ConfigError::Io(path, error)),
+        }
+    }
+
+    /// Parses a table of action names to lists of key names, like `hold = ["C", "Left Shift"]`,
+    /// with a `[controller]` table of the same for controller inputs, plus a `deadzone`,
+    /// and a `[handling]` table of `das`, `arr` and `dcd` in milliseconds.
+    /// Actions and handling that aren't mentioned keep their defaults, unless another action takes them.
+    pub fn parse(text: &str) -> Result<Self, ConfigError> {
+        let mut table: Table = toml::from_str(text).map_err(ConfigError::Parse)?;
+        let mut bindings = Self::default();
+
+        if let Some(handling) = table.remove("handling") {
+            let Value::Table(handling) = handling else {
+                return Err(ConfigError::NotATable("handling"));
+            };
+            for (name, value) in handling {
+                let field = match name.as_str() {
+                    "das" => &mut bindings.handling.das,
+                    "arr" => &mut bindings.handling.arr,
+                    "dcd" => &mut bindings.handling.dcd,
+                    _ => return Err(ConfigError::UnknownHandling(name)),
+                };
+                *field = value.as_integer()
+                    .and_then(|millis| u64::try_from(millis).ok())
+                    .map(Duration::from_millis)
+                    .ok_or(ConfigError::InvalidHandling(name, value))?;
+            }
+        }
+
+        if let Some(controller) = table.remove("controller") {
+            let Value::Table(mut controller) = controller else {
+                return Err(ConfigError::NotATable("controller"));
+            };
+            if let Some(deadzone) = controller.remove("deadzone") {
+                bindings.deadzone = deadzone.as_integer()
+                    .and_then(|deadzone| i16::try_from(deadzone).ok())
+                    .filter(|deadzone| *deadzone >= 0)
+                    .ok_or(ConfigError::InvalidDeadzone(deadzone))?;
+            }
+            rebind(&mut bindings.pad, controller, |name| {
+                PadControl::from_name(name).ok_or_else(|| ConfigError::UnknownControl(name.to_owned()))
+            })?;
+        }
+
+        rebind(&mut bindings.keys, table, |name| {
+            Keycode::from_name(name).ok_or_else(|| ConfigError::UnknownKey(name.to_owned()))
+        })?;
+
+        Ok(bindings)
+    }
+}
+
+/// Replaces the bindings of every action in `table` with the inputs it lists.
+fn rebind<T: Copy + Eq + Hash>(
+    bound: &mut HashMap<T, Input>,
+    table: Table,
+    parse: impl Fn(&str) -> Result<T, ConfigError>,
+) -> Result<(), ConfigError> {
+    let mut actions = Vec::with_capacity(table.len());
+    for (action, names) in table {
+        let input = Input::from_name(&action).ok_or(ConfigError::UnknownAction(action))?;
+        let names: Vec<String> = names.try_into().map_err(ConfigError::Parse)?;
+        actions.push((input, names));
+    }
+
+    bound.retain(|_, input| !actions.iter().any(|(rebound, _)| rebound == input));
+
+    let mut taken = HashMap::new();
+    for (input, names) in actions {
+        for name in names {
+            let control = parse(&name)?;
+            if let Some(other) = taken.insert(control, input).filter(|&other| other != input) {
+                return Err(ConfigError::Conflict(name, other, input));
+            }
+            bound.insert(control, input);
+        }
+    }
+
+    Ok(())
+}
+
+impl Default for Bindings {
+    fn default() -> Self {
+        let keys = HashMap::from([
+            (Keycode::Left, Input::Move(MoveKind::Left)),
+            (Keycode::Right, Input::Move(MoveKind::Right)),
+            (Keycode::X, Input::Rotate(RotateKind::Clockwise)),
+            (Keycode::Z, Input::Rotate(RotateKind::CounterClockwise)),
+            (Keycode::A, Input::Rotate(RotateKind::Half)),
+            (Keycode::C, Input::Hold),
+            (Keycode::LShift, Input::Hold),
+            (Keycode::Down, Input::SoftDrop),
+            (Keycode::Up, Input::HardDrop),
+            (Keycode::Space, Input::HardDrop),
+            (Keycode::Escape, Input::Pause),
+            (Keycode::R, Input::Restart),
+            (Keycode::Backspace, Input::Undo),
+            (Keycode::Return, Input::Redo),
+        ]);
+
+        let stick = |axis, direction| PadControl::Axis(axis, direction);
+        let pad = HashMap::from([
+            (PadControl::Button(Button::DPadLeft), Input::Move(MoveKind::Left)),
+            (stick(Axis::LeftX, Direction::Negative), Input::Move(MoveKind::Left)),
+            (PadControl::Button(Button::DPadRight), Input::Move(MoveKind::Right)),
+            (stick(Axis::LeftX, Direction::Positive), Input::Move(MoveKind::Right)),
+            (PadControl::Button(Button::B), Input::Rotate(RotateKind::Clockwise)),
+            (PadControl::Button(Button::A), Input::Rotate(RotateKind::CounterClockwise)),
+            (PadControl::Button(Button::Y), Input::Rotate(RotateKind::Half)),
+            (PadControl::Button(Button::X), Input::Hold),
+            (PadControl::Button(Button::LeftShoulder), Input::Hold),
+            (PadControl::Button(Button::RightShoulder), Input::Hold),
+            (PadControl::Button(Button::DPadDown), Input::SoftDrop),
+            // Down on a stick is positive
+            (stick(Axis::LeftY, Direction::Positive), Input::SoftDrop),
+            (PadControl::Button(Button::DPadUp), Input::HardDrop),
+            (stick(Axis::TriggerRight, Direction::Positive), Input::HardDrop),
+            (PadControl::Button(Button::Start), Input::Pause),
+            (PadControl::Button(Button::Back), Input::Restart),
+            (PadControl::Button(Button::LeftStick), Input::Undo),
+            (PadControl::Button(Button::RightStick), Input::Redo),
+        ]);
+
+        Self {
+            keys,
+            pad,
+            deadzone: 8000,
+            handling: Handling::default(),
+        }
+    }
+}
+
+/// Why the bindings file couldn't be used.
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(PathBuf, io::Error),
+    Parse(toml::de::Error),
+    UnknownAction(String),
+    UnknownKey(String),
+    UnknownControl(String),
+    NotATable(&'static str),
+    InvalidDeadzone(Value),
+    UnknownHandling(String),
+    InvalidHandling(String, Value),
+    /// A key or control was bound to two different actions.
+    Conflict(String, Input, Input),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(path, error) => write!(f, "Couldn't read {}: {}", path.display(), error),
+            ConfigError::Parse(error) => write!(f, "Invalid bindings file: {}", error),
+            ConfigError::UnknownAction(action) => {
+                let actions = Input::NAMED.map(|(name, _)| name).join(", ");
+                write!(f, "Unknown action \"{}\", expected one of: {}", action, actions)
+            }
+            ConfigError::UnknownKey(name) => write!(f, "Unknown key \"{}\"", name),
+            ConfigError::UnknownControl(name) => write!(f, "Unknown controller input \"{}\"", name),
+            ConfigError::NotATable(name) => write!(f, "Expected [{}] to be a table", name),
+            ConfigError::InvalidDeadzone(value) => {
+                write!(f, "Deadzone should be a whole number from 0 to {}, not {}", i16::MAX, value)
+            }
+            ConfigError::UnknownHandling(name) => {
+                write!(f, "Unknown handling setting \"{}\", expected one of: das, arr, dcd", name)
+            }
+            ConfigError::InvalidHandling(name, value) => {
+                write!(f, "{} should be a whole number of milliseconds, not {}", name, value)
+            }
+            ConfigError::Conflict(name, first, second) => write!(
+                f,
+                "\"{}\" is bound to both {} and {}",
+                name,
+                first.name(),
+                second.name(),
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn every_action_has_a_default_key() {
+        let bindings = Bindings::default();
+        for (name, input) in Input::NAMED {
+            assert!(bindings.keys.values().any(|&bound| bound == input), "{}", name);
+            assert!(bindings.pad.values().any(|&bound| bound == input), "{}", name);
+        }
+    }
+
+    #[test]
+    fn rebinding_replaces_default_keys() {
+        let bindings = Bindings::parse(r#"
+            hard_drop = ["Space"]
+            rotate_clockwise = ["Up", "X"]
+        "#).unwrap();
+
+        assert_eq!(bindings.key(Keycode::Up), Some(Input::Rotate(RotateKind::Clockwise)));
+        assert_eq!(bindings.key(Keycode::X), Some(Input::Rotate(RotateKind::Clockwise)));
+        assert_eq!(bindings.key(Keycode::Space), Some(Input::HardDrop));
+        assert_eq!(bindings.key(Keycode::Left), Some(Input::Move(MoveKind::Left)));
+    }
+
+    #[test]
+    fn invalid_files_are_errors() {
+        assert!(matches!(Bindings::parse("hold = "), Err(ConfigError::Parse(_))));
+        assert!(matches!(
+            Bindings::parse(r#"spin = ["X"]"#),
+            Err(ConfigError::UnknownAction(action)) if action == "spin",
+        ));
+        assert!(matches!(
+            Bindings::parse(r#"hold = ["Hyper Shift"]"#),
+            Err(ConfigError::UnknownKey(key)) if key == "Hyper Shift",
+        ));
+        assert!(matches!(
+            Bindings::parse(r#"hold = ["X"]
+            pause = ["X"]"#),
+            Err(ConfigError::Conflict(key, _, _)) if key == "X",
+        ));
+        assert!(matches!(
+            Bindings::parse("[controller]\ndeadzone = 40000"),
+            Err(ConfigError::InvalidDeadzone(_)),
+        ));
+    }
+
+    #[test]
+    fn handling_section() {
+        let bindings = Bindings::parse(r#"
+            [handling]
+            das = 100
+            arr = 0
+        "#).unwrap();
+
+        assert_eq!(bindings.handling.das, Duration::from_millis(100));
+        assert_eq!(bindings.handling.arr, Duration::ZERO);
+        assert_eq!(bindings.handling.dcd, Handling::default().dcd);
+
+        assert!(matches!(
+            Bindings::parse("[handling]\ndas = -5"),
+            Err(ConfigError::InvalidHandling(name, _)) if name == "das",
+        ));
+        assert!(matches!(
+            Bindings::parse("[handling]\nsdf = 20"),
+            Err(ConfigError::UnknownHandling(name)) if name == "sdf",
+        ));
+    }
+
+    #[test]
+    fn controller_section() {
+        let bindings = Bindings::parse(r#"
+            hold = ["C"]
+
+            [controller]
+            deadzone = 12000
+            hold = ["leftshoulder"]
+            hard_drop = ["dpup", "lefty-"]
+        "#).unwrap();
+
+        assert_eq!(bindings.deadzone, 12000);
+        assert_eq!(bindings.key(Keycode::C), Some(Input::Hold));
+        assert_eq!(bindings.key(Keycode::LShift), None);
+        assert_eq!(bindings.pad(PadControl::Button(Button::LeftShoulder)), Some(Input::Hold));
+        assert_eq!(bindings.pad(PadControl::Button(Button::X)), None);
+        assert_eq!(
+            bindings.pad(PadControl::Axis(Axis::LeftY, Direction::Negative)),
+            Some(Input::HardDrop),
+        );
+        assert_eq!(bindings.pad(PadControl::Axis(Axis::TriggerRight, Direction::Positive)), None);
+    }
+}
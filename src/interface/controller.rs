@@ -0,0 +1,97 @@
+use std::collections::HashSet;
+
+use sdl2::controller::{Axis, Button};
+
+/// A game controller input that can be bound to an action:
+/// a button, or an analog stick or trigger pushed one way past the deadzone.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum PadControl {
+    Button(Button),
+    Axis(Axis, Direction),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Direction {
+    Positive,
+    Negative,
+}
+
+impl PadControl {
+    /// Parses SDL's mapping names, like `dpleft` or `a`, with axes given a sign like `leftx-`.
+    /// Triggers only go one way, so they don't need one.
+    pub fn from_name(name: &str) -> Option<Self> {
+        if let Some(button) = Button::from_string(name) {
+            return Some(Self::Button(button));
+        }
+
+        let (axis, direction) = match name.strip_suffix(['+', '-']) {
+            Some(axis) if name.ends_with('+') => (axis, Direction::Positive),
+            Some(axis) => (axis, Direction::Negative),
+            None => (name, Direction::Positive),
+        };
+        let axis = Axis::from_string(axis)?;
+
+        let is_trigger = matches!(axis, Axis::TriggerLeft | Axis::TriggerRight);
+        let signed = name != axis.string();
+        (is_trigger != signed).then_some(Self::Axis(axis, direction))
+    }
+}
+
+/// Turns analog axis positions into presses and releases.
+#[derive(Clone, Debug, Default)]
+pub struct AxisTracker {
+    held: HashSet<PadControl>,
+}
+
+impl AxisTracker {
+    /// Moves `axis` to `value`, returning the controls that changed and whether they're now held.
+    pub fn update(&mut self, axis: Axis, value: i16, deadzone: i16) -> Vec<(PadControl, bool)> {
+        let mut changes = Vec::new();
+        for direction in [Direction::Positive, Direction::Negative] {
+            let control = PadControl::Axis(axis, direction);
+            let held = match direction {
+                Direction::Positive => value > deadzone,
+                Direction::Negative => value < -deadzone,
+            };
+
+            let was_held = if held {
+                !self.held.insert(control)
+            } else {
+                self.held.remove(&control)
+            };
+            if held != was_held {
+                changes.push((control, held));
+            }
+        }
+        changes
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn names() {
+        assert_eq!(PadControl::from_name("dpleft"), Some(PadControl::Button(Button::DPadLeft)));
+        assert_eq!(PadControl::from_name("leftx-"), Some(PadControl::Axis(Axis::LeftX, Direction::Negative)));
+        assert_eq!(PadControl::from_name("lefty+"), Some(PadControl::Axis(Axis::LeftY, Direction::Positive)));
+        assert_eq!(PadControl::from_name("righttrigger"), Some(PadControl::Axis(Axis::TriggerRight, Direction::Positive)));
+        assert_eq!(PadControl::from_name("leftx"), None);
+        assert_eq!(PadControl::from_name("righttrigger-"), None);
+        assert_eq!(PadControl::from_name("turbo"), None);
+    }
+
+    #[test]
+    fn axes_press_past_the_deadzone() {
+        let mut tracker = AxisTracker::default();
+        let left = PadControl::Axis(Axis::LeftX, Direction::Negative);
+        let right = PadControl::Axis(Axis::LeftX, Direction::Positive);
+
+        assert_eq!(tracker.update(Axis::LeftX, -4000, 8000), []);
+        assert_eq!(tracker.update(Axis::LeftX, -9000, 8000), [(left, true)]);
+        assert_eq!(tracker.update(Axis::LeftX, -20000, 8000), []);
+        assert_eq!(tracker.update(Axis::LeftX, 20000, 8000), [(right, true), (left, false)]);
+        assert_eq!(tracker.update(Axis::LeftX, 0, 8000), [(right, false)]);
+    }
+}
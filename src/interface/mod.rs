@@ -1,136 +1,313 @@
 mod render_traits;
 mod sub_rect;
-mod sync_events;
+mod auto_shift;
+mod bindings;
+mod controller;
+mod replays;
+mod playback;
 
-use std::{time::Duration, sync::{Arc, Mutex}};
+use std::time::{Duration, Instant};
 
 use cgmath::{Vector2, ElementWise, EuclideanSpace, Point2};
-use sdl2::{event::Event, rect::Rect, render::Canvas, video::Window, pixels::Color, keyboard::Keycode};
+use sdl2::{event::Event, rect::Rect, render::{Canvas, BlendMode}, video::Window, pixels::Color};
 
-use crate::{engine::{Engine, Matrix, Color as SemanticColor, MoveKind}, interface::sync_events::SyncEvents};
+use tehtrys::engine::{
+    Engine, Color as SemanticColor, piece::Piece, EngineEvent, Phase, TopOut, stats::TSpin,
+    replay::{Setup, Recorder, Action},
+};
 
-use self::{render_traits::ScreenColor, sub_rect::{SubRect, Align}};
+use self::{render_traits::ScreenColor, sub_rect::{SubRect, Align}, auto_shift::{AutoShift, Shift}, bindings::Input, controller::{PadControl, AxisTracker}};
+
+pub use self::{bindings::Bindings, playback::play_back, replays::load_replay};
 
 const INIT_SIZE: Vector2<u32> = Vector2::new(1024, 1024);
 const BACKGROUND_COLOR: Color = Color::RGB(0x10, 0x10, 0x18);
 const PLACEHOLDER_1: Color = Color::RGB(0x66, 0x77, 0x77);
 const PLACEHOLDER_2: Color = Color::RGB(0x77, 0x88, 0x88);
+const DISABLED_COLOR: Color = Color::RGB(0x44, 0x44, 0x4c);
+/// The score panel's colour in practice games, so their results are never mistaken for real ones.
+const PRACTICE_COLOR: Color = Color::RGB(0x8a, 0x6a, 0x3a);
+const PREVIEW_CELLS: u32 = 4;
+const GHOST_ALPHA: u8 = 0x50;
+const CLEAR_FLASH_COLOR: Color = Color::RGB(0xee, 0xee, 0xf4);
+/// How much of the first hidden row peeks out above the matrix, as a fraction of a cell.
+const PEEK_DIVISOR: u32 = 3;
+
+/// Player preferences for the front end.
+#[derive(Clone, Debug)]
+pub struct Settings {
+    /// How many upcoming pieces to show, between 0 and [`Settings::MAX_PREVIEW`].
+    pub preview_count: usize,
+    pub ghost: GhostStyle,
+    /// Keys and controls, along with the handling of held directions.
+    pub bindings: Bindings,
+}
+
+/// How to show where the cursor would land.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum GhostStyle {
+    Off,
+    Outline,
+    Translucent,
+}
 
-struct Tick;
-struct LockdownTick;
-struct SoftDropTick;
+impl Settings {
+    pub const MAX_PREVIEW: usize = 7;
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            preview_count: 5,
+            ghost: GhostStyle::Translucent,
+            bindings: Bindings::default(),
+        }
+    }
+}
+
+/// The simulation always advances in steps of this size, however fast frames are drawn.
+const TIMESTEP: Duration = Duration::from_nanos(1_000_000_000 / 60);
+/// After a stall, catch up on at most this much time rather than fast forwarding.
+const MAX_FRAME_TIME: Duration = Duration::from_millis(250);
+
+fn open_canvas(sdl: &sdl2::Sdl) -> Canvas<Window> {
+    let video = sdl.video().expect("Failed to acquire display");
+
+    let window = video
+        .window("Tehtrys", INIT_SIZE.x, INIT_SIZE.y)
+        .position_centered()
+        .resizable()
+        .build()
+        .expect("Failed to create window");
+
+    window
+        .into_canvas()
+        .accelerated()
+        .present_vsync()
+        .build()
+        .expect("Failed to get render canvas")
+}
+
+/// Plays games set up by `new_setup` until the window is closed, starting a fresh one on restart.
+/// Every game is recorded, and its replay saved once it's over.
+pub fn run(new_setup: impl Fn() -> Setup, mut settings: Settings) {
+    settings.preview_count = settings.preview_count.min(Settings::MAX_PREVIEW);
 
-pub fn run(mut engine: Engine) {
     let sdl = sdl2::init().expect("Failed to initialize SDL2");
+    let mut canvas = open_canvas(&sdl);
 
-    let event_subsystem = SyncEvents::from(
-        sdl.event().expect("Failed to acquire event subsystem")
-    );
-    event_subsystem.register_custom_event::<Tick>().unwrap();
-    event_subsystem.register_custom_event::<LockdownTick>().unwrap();
-
-    // let timer_subsystem = sdl.timer().expect("Failed to acquire timer subsystem");
-
-    let mut canvas = {
-        let video = sdl.video().expect("Failed to acquire display");
-
-        let window = video
-            .window("Tehtrys", INIT_SIZE.x, INIT_SIZE.y)
-            .position_centered()
-            .resizable()
-            .build()
-            .expect("Failed to create window");
-
-        window
-            .into_canvas()
-            .accelerated()
-            .present_vsync()
-            .build()
-            .expect("Failed to get render canvas")
-    };
+    let controller_subsystem = sdl.game_controller().expect("Failed to initialize game controllers");
+    // Controllers only send events while they're open
+    let mut controllers = Vec::new();
+    let mut axes = AxisTracker::default();
 
     let mut events = sdl.event_pump().expect("Failed to get event loop");
 
-    event_subsystem.push_custom_event(Tick).unwrap();
+    let (mut recorder, mut engine) = Recorder::start(new_setup(), TIMESTEP);
+    let mut game_over = None;
+    let mut paused = false;
+
+    let mut auto_shift = AutoShift::new(settings.bindings.handling);
+    // Simulated time, so held keys are timed in steps like everything else
+    let mut clock = Duration::ZERO;
 
-    let mut dirty = true;
-    let mut lock_down = false;
+    let mut last_frame = Instant::now();
+    let mut accumulator = Duration::ZERO;
 
     loop {
+        let was_over = game_over.is_some();
+
+        let now = Instant::now();
+        accumulator += (now - last_frame).min(MAX_FRAME_TIME);
+        last_frame = now;
+
+        // Keyboard and controller both come down to pressing and releasing inputs
+        let mut presses = Vec::new();
         for event in events.poll_iter() {
             match event {
-                Event::Quit { .. } => return,
-                Event::User { .. } if event.as_user_event_type::<Tick>().is_some() => {
-                    println!("Found tick event");
-                    dirty = true;
-                }
-                Event::User { .. } if event.as_user_event_type::<LockdownTick>().is_some() => {
-                    println!("Found lockdown tick event");
-                    dirty = true;
-                    lock_down = true;
-                }
-                Event::User { .. } if event.as_user_event_type::<SoftDropTick>().is_some() => {
-                    println!("Found soft drop tick event");
-                    dirty = true;
-                }
-                Event::KeyDown { keycode: Some(key), .. } => {
-                    if let Ok(input) = Input::try_from(key) {
-                        match input {
-                            Input::Move(kind) => drop(engine.move_cursor(kind)),
-                            Input::HardDrop => {
-                                engine.hard_drop();
-                                lock_down = true;
-                            }
-                            Input::SoftDrop => todo!("Soft drop ticks"),
-                        }
-                        dirty = true;
+                Event::Quit { .. } => {
+                    replays::save_finished(recorder.finish());
+                    return;
+                }
+                // Held keys are handled by auto shift, not the OS's key repeat
+                Event::KeyDown { keycode: Some(key), repeat: false, .. } => {
+                    presses.extend(settings.bindings.key(key).map(|input| (input, true)));
+                }
+                Event::KeyUp { keycode: Some(key), .. } => {
+                    presses.extend(settings.bindings.key(key).map(|input| (input, false)));
+                }
+                Event::ControllerButtonDown { button, .. } => {
+                    presses.extend(settings.bindings.pad(PadControl::Button(button)).map(|input| (input, true)));
+                }
+                Event::ControllerButtonUp { button, .. } => {
+                    presses.extend(settings.bindings.pad(PadControl::Button(button)).map(|input| (input, false)));
+                }
+                Event::ControllerAxisMotion { axis, value, .. } => {
+                    for (control, held) in axes.update(axis, value, settings.bindings.deadzone) {
+                        presses.extend(settings.bindings.pad(control).map(|input| (input, held)));
                     }
                 }
+                Event::ControllerDeviceAdded { which, .. } => match controller_subsystem.open(which) {
+                    Ok(controller) => controllers.push(controller),
+                    Err(error) => eprintln!("Couldn't open controller {}: {}", which, error),
+                }
+                Event::ControllerDeviceRemoved { which, .. } => {
+                    controllers.retain(|controller| controller.instance_id() != which);
+                }
                 _ => {}
             }
         }
 
-        if lock_down {
-            engine.line_clear(|_| ());
+        for (input, pressed) in presses {
+            if !pressed {
+                match input {
+                    Input::SoftDrop => {
+                        let _ = recorder.act(&mut engine, Action::SoftDrop(false));
+                    }
+                    Input::Move(kind) => auto_shift.release(kind, clock),
+                    _ => {}
+                }
+                continue;
+            }
+
+            match input {
+                Input::Restart => {
+                    let (next, next_engine) = Recorder::start(new_setup(), TIMESTEP);
+                    replays::save_finished(std::mem::replace(&mut recorder, next).finish());
+                    engine = next_engine;
+                    game_over = None;
+                    paused = false;
+                    auto_shift = AutoShift::new(settings.bindings.handling);
+                }
+                Input::Pause if game_over.is_none() => paused = !paused,
+                // Undo can take back topping out, so it works once the game's over
+                Input::Undo | Input::Redo if !paused => {
+                    let action = if input == Input::Undo { Action::Undo } else { Action::Redo };
+                    if recorder.act(&mut engine, action) == Ok(true) {
+                        game_over = None;
+                        auto_shift.cut(clock);
+                    }
+                }
+                _ if game_over.is_some() || paused => {}
+                Input::Move(kind) => auto_shift.press(kind, clock),
+                Input::Rotate(kind) => if recorder.act(&mut engine, Action::Rotate(kind)) == Ok(true) {
+                    auto_shift.cut(clock);
+                }
+                Input::Hold => if let Err(top_out) = recorder.act(&mut engine, Action::Hold) {
+                    game_over = Some(top_out);
+                }
+                Input::HardDrop => if let Err(top_out) = recorder.act(&mut engine, Action::HardDrop) {
+                    game_over = Some(top_out);
+                }
+                Input::SoftDrop => {
+                    let _ = recorder.act(&mut engine, Action::SoftDrop(true));
+                }
+                Input::Pause | Input::Undo | Input::Redo => {}
+            }
+        }
+
+        while accumulator >= TIMESTEP {
+            accumulator -= TIMESTEP;
+
+            if game_over.is_some() || paused {
+                continue;
+            }
+            clock += TIMESTEP;
+
+            match auto_shift.update(clock) {
+                Some(Shift::By(kind, count)) => for _ in 0..count {
+                    if recorder.act(&mut engine, Action::Move(kind)) != Ok(true) {
+                        break;
+                    }
+                }
+                Some(Shift::ToWall(kind)) => while recorder.act(&mut engine, Action::Move(kind)) == Ok(true) {}
+                None => {}
+            }
+
+            if let Err(top_out) = recorder.step(&mut engine) {
+                game_over = Some(top_out);
+            }
+        }
+
+        for event in engine.drain_events() {
+            if let EngineEvent::PieceSpawned(_) = event {
+                auto_shift.cut(clock);
+            }
+            print_callout(&event);
+        }
+
+        if let (false, Some(top_out)) = (was_over, game_over) {
+            print_game_over(top_out, &engine);
         }
 
-        if dirty {
-            draw(&mut canvas, &engine);
-            dirty = false;
+        draw(&mut canvas, &mut engine, &settings);
+
+        // Vsync normally paces the loop, but don't spin if it isn't available
+        let frame_time = last_frame.elapsed();
+        if frame_time < TIMESTEP {
+            std::thread::sleep(TIMESTEP - frame_time);
         }
     }
 }
 
-enum Input {
-    Move(MoveKind),
-    SoftDrop,
-    HardDrop,
+fn print_game_over(top_out: TopOut, engine: &Engine) {
+    let stats = engine.stats();
+    print!("Game over: {:?}, scoring {}", top_out, stats.score);
+    if stats.is_legit() {
+        println!();
+    } else {
+        println!(" in practice, with {} undos", stats.undos);
+    }
 }
 
-impl TryFrom<Keycode> for Input {
-    type Error = ();
+fn print_callout(event: &EngineEvent) {
+    if let Some(callout) = callout(event) {
+        println!("{}", callout);
+    }
+}
 
-    fn try_from(key: Keycode) -> Result<Self, Self::Error> {
-        Ok(match key {
-            Keycode::Right => Self::Move(MoveKind::Right),
-            Keycode::Left => Self::Move(MoveKind::Left),
-            Keycode::Up => Self::HardDrop,
-            Keycode::Down => Self::SoftDrop,
-            _ => return Err(()),
-        })
+/// Text worth showing the player for notable events.
+fn callout(event: &EngineEvent) -> Option<String> {
+    match event {
+        EngineEvent::LinesCleared { chain, .. } if *chain > 0 => Some(format!("{} chain", chain)),
+        EngineEvent::LinesCleared { rows, tspin, combo, back_to_back, .. } => {
+            let clear = match (tspin, rows.len()) {
+                (TSpin::None, 4) => "Tetris".to_owned(),
+                (TSpin::None, _) => return combo.filter(|&combo| combo > 0).map(|combo| format!("{} combo", combo)),
+                (TSpin::Mini, 0) => "Mini T-spin".to_owned(),
+                (TSpin::Mini, lines) => format!("Mini T-spin {}", line_count_name(lines)),
+                (TSpin::Full, 0) => "T-spin".to_owned(),
+                (TSpin::Full, lines) => format!("T-spin {}", line_count_name(lines)),
+            };
+            Some(if *back_to_back { format!("Back-to-back {}", clear) } else { clear })
+        }
+        EngineEvent::PerfectClear => Some("Perfect clear".to_owned()),
+        EngineEvent::LevelUp(level) => Some(format!("Level {}", level)),
+        _ => None,
     }
 }
 
-fn draw(canvas: &mut Canvas<Window>, engine: &Engine) {
+fn line_count_name(lines: usize) -> &'static str {
+    match lines {
+        1 => "single",
+        2 => "double",
+        _ => "triple",
+    }
+}
+
+fn draw(canvas: &mut Canvas<Window>, engine: &mut Engine, settings: &Settings) {
     canvas.set_draw_color(BACKGROUND_COLOR);
     canvas.clear();
 
     let viewport = canvas.viewport();
     let ui_square = SubRect::absolute(viewport, (1.0, 1.0), None);
 
-    let matrix = ui_square
+    let cell_count = Vector2::new(engine.matrix().width(), engine.matrix().visible_height())
+        .cast::<u32>().unwrap();
+    let matrix_area = ui_square
         .sub_rect((0.5, 1.0), None)
         .sub_rect((7.0/8.0, 7.0/8.0), None);
+    let matrix = fit_matrix(&matrix_area, cell_count);
 
     let up_next = ui_square
         .sub_rect((0.25, 0.25), Some((Align::Far, Align::Near)))
@@ -156,18 +333,52 @@ fn draw(canvas: &mut Canvas<Window>, engine: &Engine) {
 
     canvas.set_draw_color(PLACEHOLDER_1);
 
-    for subrect in [&matrix, &up_next, &hold, &queue, &score] {
+    for subrect in [&matrix, &up_next, &hold, &queue] {
         canvas.fill_rect(Rect::from(subrect)).unwrap();
     }
+    if !engine.stats().is_legit() {
+        canvas.set_draw_color(PRACTICE_COLOR);
+    }
+    canvas.fill_rect(Rect::from(&score)).unwrap();
 
     let mut cell_ctx = CellDrawContext {
         origin: matrix.bottom_left(),
         dims: matrix.size(),
+        cell_count,
         canvas,
     };
 
+    // Anything higher up in the buffer is hidden, apart from a sliver of its first row
+    let peek = matrix.size().y / cell_count.y / PEEK_DIVISOR;
+    let matrix_rect = Rect::from(&matrix);
+    cell_ctx.canvas.set_clip_rect(Rect::new(
+        matrix_rect.x(),
+        matrix_rect.y() - peek as i32,
+        matrix_rect.width(),
+        matrix_rect.height() + peek,
+    ));
+
+    // Rows waiting to be cleared flash, then dissolve from the middle out
+    let clearing = match engine.phase() {
+        Phase::LineClearDelay { rows, elapsed, .. } => {
+            let delay = engine.rules().line_clear_delay;
+            let progress = if delay.is_zero() { 1.0 } else { elapsed.as_secs_f32() / delay.as_secs_f32() };
+            Some((rows, progress))
+        }
+        _ => None,
+    };
+
     for (coord, cell) in engine.cells() {
-        cell_ctx.try_draw_cell(coord, cell);
+        match &clearing {
+            Some((rows, progress)) if rows.contains(&coord.y) => cell_ctx.draw_clearing_cell(coord, *progress),
+            _ => cell_ctx.try_draw_cell(coord, cell),
+        }
+    }
+
+    if let Some((ghost_cells, ghost_color)) = engine.ghost_info() {
+        for coord in ghost_cells {
+            cell_ctx.draw_ghost_cell(coord, ghost_color, settings.ghost);
+        }
     }
 
     if let Some((cursor_cells, cursor_color)) = engine.cursor_info() {
@@ -176,18 +387,97 @@ fn draw(canvas: &mut Canvas<Window>, engine: &Engine) {
         }
     }
 
+    cell_ctx.canvas.set_clip_rect(None);
+
+    if let Some(kind) = engine.held_piece() {
+        let color = if engine.hold_used() {
+            DISABLED_COLOR
+        } else {
+            kind.color().screen_color()
+        };
+        draw_piece(canvas, &hold, engine.spawn_shape(kind), color);
+    }
+
+    let preview = engine.preview(settings.preview_count).collect::<Vec<_>>();
+    let queue_slots = queue_slots(&queue);
+    let preview_areas = std::iter::once(up_next).chain(queue_slots);
+    for (kind, area) in preview.into_iter().zip(preview_areas) {
+        draw_piece(canvas, &area, engine.spawn_shape(kind), kind.color().screen_color());
+    }
+
     canvas.present();
 }
 
+/// Shrinks `area`, which fits a standard 10 by 20 matrix, to the aspect ratio of one `cell_count` in size.
+fn fit_matrix(area: &SubRect, cell_count: Vector2<u32>) -> SubRect {
+    let aspect = cell_count.x as f32 / cell_count.y as f32;
+    let standard = 0.5;
+    let ratio = if aspect < standard {
+        (aspect / standard, 1.0)
+    } else {
+        (1.0, standard / aspect)
+    };
+    area.sub_rect(ratio, None)
+}
+
+/// Splits the queue panel into evenly sized slots, top to bottom,
+/// for everything in the preview after the piece that's up next.
+fn queue_slots(queue: &SubRect) -> impl Iterator<Item = SubRect> {
+    let area = Rect::from(queue);
+    let slot_count = Settings::MAX_PREVIEW as u32 - 1;
+    let slot_height = area.height() / slot_count;
+
+    (0..slot_count).map(move |slot| {
+        let slot_rect = Rect::new(
+            area.x(),
+            area.y() + (slot * slot_height) as i32,
+            area.width(),
+            slot_height,
+        );
+        SubRect::of(slot_rect, (1.0, 1.0), None)
+    })
+}
+
+/// Draws a lone piece centred in `area`, at a scale where the widest piece fits.
+fn draw_piece(
+    canvas: &mut Canvas<Window>,
+    area: &SubRect,
+    cells: [Vector2<isize>;Piece::CELL_COUNT],
+    color: Color,
+) {
+    let min = cells.into_iter().reduce(|a, b| Vector2::new(a.x.min(b.x), a.y.min(b.y))).unwrap();
+    let max = cells.into_iter().reduce(|a, b| Vector2::new(a.x.max(b.x), a.y.max(b.y))).unwrap();
+    let extent = (max - min + Vector2::new(1, 1)).cast::<i32>().unwrap();
+
+    let size = area.size();
+    let cell_size = (size.x.min(size.y) / PREVIEW_CELLS) as i32;
+    let top_left = area.top_left();
+    let bottom_left = Point2::new(
+        top_left.x + (size.x as i32 - extent.x * cell_size) / 2,
+        top_left.y + (size.y as i32 + extent.y * cell_size) / 2,
+    );
+
+    canvas.set_draw_color(color);
+    for cell in cells {
+        let cell = (cell - min).cast::<i32>().unwrap();
+        let cell_rect = Rect::new(
+            bottom_left.x + cell.x * cell_size,
+            bottom_left.y - (cell.y + 1) * cell_size,
+            cell_size as u32,
+            cell_size as u32,
+        );
+        canvas.fill_rect(cell_rect).unwrap();
+    }
+}
+
 struct CellDrawContext<'canvas> {
     origin: Point2<i32>,
     dims: Vector2<u32>,
+    cell_count: Vector2<u32>,
     canvas: &'canvas mut Canvas<Window>,
 }
 
 impl CellDrawContext<'_> {
-    const CELL_COUNT: Vector2<u32> = Vector2::new(Matrix::WIDTH as u32, Matrix::HEIGHT as u32);
-
     fn try_draw_cell(
         &mut self,
         coord: Point2<usize>,
@@ -202,17 +492,59 @@ impl CellDrawContext<'_> {
         coord: Point2<usize>,
         color: SemanticColor,
     ) {
+        let cell_rect = self.cell_rect(coord);
+        self.canvas.set_draw_color(color.screen_color());
+        self.canvas.fill_rect(cell_rect).unwrap();
+    }
+
+    fn draw_clearing_cell(
+        &mut self,
+        coord: Point2<usize>,
+        progress: f32,
+    ) {
+        let half_width = self.cell_count.x as f32 / 2.0;
+        let from_middle = (coord.x as f32 + 0.5 - half_width).abs() / half_width;
+        if from_middle < progress {
+            return;
+        }
+
+        let cell_rect = self.cell_rect(coord);
+        self.canvas.set_draw_color(CLEAR_FLASH_COLOR);
+        self.canvas.fill_rect(cell_rect).unwrap();
+    }
+
+    fn draw_ghost_cell(
+        &mut self,
+        coord: Point2<usize>,
+        color: SemanticColor,
+        style: GhostStyle,
+    ) {
+        let cell_rect = self.cell_rect(coord);
+        let color = color.screen_color();
+        match style {
+            GhostStyle::Off => {}
+            GhostStyle::Outline => {
+                self.canvas.set_draw_color(color);
+                self.canvas.draw_rect(cell_rect).unwrap();
+            }
+            GhostStyle::Translucent => {
+                self.canvas.set_blend_mode(BlendMode::Blend);
+                self.canvas.set_draw_color(Color::RGBA(color.r, color.g, color.b, GHOST_ALPHA));
+                self.canvas.fill_rect(cell_rect).unwrap();
+                self.canvas.set_blend_mode(BlendMode::None);
+            }
+        }
+    }
+
+    fn cell_rect(&self, coord: Point2<usize>) -> Rect {
         let coord = coord.to_vec().cast::<u32>().unwrap();
-        let this = (coord + Vector2::new(0, 1)).mul_element_wise(self.dims).div_element_wise(Self::CELL_COUNT);
-        let next = (coord + Vector2::new(1, 0)).mul_element_wise(self.dims).div_element_wise(Self::CELL_COUNT);
-        let cell_rect = Rect::new(
+        let this = (coord + Vector2::new(0, 1)).mul_element_wise(self.dims).div_element_wise(self.cell_count);
+        let next = (coord + Vector2::new(1, 0)).mul_element_wise(self.dims).div_element_wise(self.cell_count);
+        Rect::new(
             self.origin.x + this.x as i32,
             self.origin.y - this.y as i32,
             next.x - this.x,
             this.y - next.y,
-        );
-
-        self.canvas.set_draw_color(color.screen_color());
-        self.canvas.fill_rect(cell_rect).unwrap();
+        )
     }
 }
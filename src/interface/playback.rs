@@ -0,0 +1,86 @@
+use std::time::{Duration, Instant};
+
+use sdl2::{event::Event, keyboard::Keycode};
+
+use tehtrys::engine::replay::{Replay, Player};
+
+use super::{Settings, TIMESTEP, MAX_FRAME_TIME, open_canvas, draw, print_callout, print_game_over};
+
+/// Playback speeds to step through, as multiples of real time.
+const SPEEDS: [f32;7] = [0.125, 0.25, 0.5, 1.0, 2.0, 4.0, 8.0];
+const NORMAL_SPEED: usize = 3;
+/// How far each press of a seek key jumps.
+const SEEK_STEP: Duration = Duration::from_secs(5);
+
+/// Shows a replay until the window is closed. Playback has its own keys:
+/// space pauses, period steps a frame while paused, up and down change the speed,
+/// left and right seek, and home goes back to the start.
+pub fn play_back(replay: Replay, settings: Settings) {
+    let sdl = sdl2::init().expect("Failed to initialize SDL2");
+    let mut canvas = open_canvas(&sdl);
+    let mut events = sdl.event_pump().expect("Failed to get event loop");
+
+    let timestep = replay.timestep;
+    let seek_frames = (SEEK_STEP.as_nanos() / timestep.as_nanos()).max(1) as u32;
+    let mut player = Player::new(replay);
+    let mut paused = false;
+    let mut speed = NORMAL_SPEED;
+
+    let mut last_frame = Instant::now();
+    let mut accumulator = Duration::ZERO;
+
+    loop {
+        let was_finished = player.finished();
+
+        let now = Instant::now();
+        let elapsed = (now - last_frame).min(MAX_FRAME_TIME);
+        last_frame = now;
+        if !paused {
+            accumulator += elapsed.mul_f32(SPEEDS[speed]);
+        }
+
+        let old_speed = speed;
+        for event in events.poll_iter() {
+            match event {
+                Event::Quit { .. } => return,
+                Event::KeyDown { keycode: Some(key), .. } => match key {
+                    Keycode::Space => paused = !paused,
+                    Keycode::Period if paused => player.step(),
+                    Keycode::Up => speed = (speed + 1).min(SPEEDS.len() - 1),
+                    Keycode::Down => speed = speed.saturating_sub(1),
+                    Keycode::Left => player.seek(player.frame().saturating_sub(seek_frames)),
+                    Keycode::Right => player.seek(player.frame().saturating_add(seek_frames)),
+                    Keycode::Home => player.seek(0),
+                    _ => {}
+                }
+                _ => {}
+            }
+        }
+        if speed != old_speed {
+            println!("{}x speed", SPEEDS[speed]);
+        }
+
+        while accumulator >= timestep {
+            accumulator -= timestep;
+            player.step();
+        }
+
+        for event in player.engine_mut().drain_events() {
+            print_callout(&event);
+        }
+
+        if !was_finished && player.finished() {
+            match player.game_over() {
+                Some(top_out) => print_game_over(top_out, player.engine()),
+                None => println!("End of replay"),
+            }
+        }
+
+        draw(&mut canvas, player.engine_mut(), &settings);
+
+        let frame_time = last_frame.elapsed();
+        if frame_time < TIMESTEP {
+            std::thread::sleep(TIMESTEP - frame_time);
+        }
+    }
+}
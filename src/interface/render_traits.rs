@@ -1,5 +1,5 @@
 use sdl2::pixels::Color as SdlColor;
-use crate::engine::Color as SemanticColor;
+use tehtrys::engine::Color as SemanticColor;
 
 
 pub trait ScreenColor {
@@ -0,0 +1,53 @@
+use std::{fmt, fs, io, path::{Path, PathBuf}, time::{SystemTime, UNIX_EPOCH}};
+
+use tehtrys::engine::replay::{Replay, ReplayError};
+
+const EXTENSION: &str = "replay";
+
+/// Where finished games are saved, in the user's data directory.
+pub fn dir() -> Option<PathBuf> {
+    dirs::data_dir().map(|dir| dir.join("tehtrys").join("replays"))
+}
+
+/// Saves the replay of a game that's over, named after when it finished.
+/// Games abandoned before they got going aren't worth keeping.
+pub fn save_finished(replay: Replay) {
+    if replay.frames == 0 {
+        return;
+    }
+    match save(&replay) {
+        Ok(path) => println!("Saved replay to {}", path.display()),
+        Err(error) => eprintln!("Couldn't save replay: {}", error),
+    }
+}
+
+fn save(replay: &Replay) -> io::Result<PathBuf> {
+    let dir = dir().ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no data directory"))?;
+    fs::create_dir_all(&dir)?;
+
+    let millis = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis();
+    let path = dir.join(format!("{}.{}", millis, EXTENSION));
+    fs::write(&path, replay.to_bytes())?;
+    Ok(path)
+}
+
+/// Reads a replay in either its binary or JSON form.
+pub fn load_replay(path: &Path) -> Result<Replay, LoadError> {
+    let bytes = fs::read(path).map_err(|error| LoadError::Io(path.to_owned(), error))?;
+    Replay::parse(&bytes).map_err(|error| LoadError::Replay(path.to_owned(), error))
+}
+
+#[derive(Debug)]
+pub enum LoadError {
+    Io(PathBuf, io::Error),
+    Replay(PathBuf, ReplayError),
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadError::Io(path, error) => write!(f, "Couldn't read {}: {}", path.display(), error),
+            LoadError::Replay(path, error) => write!(f, "{}: {}", path.display(), error),
+        }
+    }
+}
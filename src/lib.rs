@@ -0,0 +1,32 @@
+//! The engine behind tehtrys: everything about a game except drawing it and reading input.
+//!
+//! An [`Engine`] moves on when it's told how much time has passed, and reports what happened as
+//! [`EngineEvent`]s, so it can be driven by a renderer, a bot, or a test just the same.
+//!
+//! ```
+//! use std::time::Duration;
+//! use tehtrys::{Engine, EngineEvent, MoveKind};
+//!
+//! let mut engine = Engine::new();
+//! engine.update(Duration::from_millis(100)).unwrap();
+//! engine.move_cursor(MoveKind::Left).unwrap();
+//! engine.hard_drop().unwrap();
+//!
+//! assert!(engine.drain_events().any(|event| matches!(event, EngineEvent::Locked(_))));
+//! ```
+//!
+//! The SDL front end is the `tehtrys` binary, behind the default `sdl` feature.
+//! Turn default features off to depend on the engine alone.
+
+#![deny(missing_docs)]
+
+/// The game itself, with everything it's made of.
+pub mod engine;
+
+pub use engine::{
+    Engine, Matrix, Color, Coordinate, Offset, MoveKind, RotateKind, MoveError, TopOut, Phase, EngineEvent,
+    Snapshot, UndoError,
+    piece::{Piece, Kind, Rotation},
+    stats::{Stats, TSpin},
+    rules::{Rules, Mode},
+};
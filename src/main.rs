@@ -1,19 +1,43 @@
 #![allow(dead_code)]
-#![feature(let_else, bool_to_option, is_sorted, array_chunks)]
 
-mod engine;
 mod interface;
 
-use engine::{Engine, Matrix, Color, piece::Kind as PieceKind};
+use std::{env, path::Path, process};
+
+use tehtrys::engine::{replay::{Setup, Replay}, rules::{Rules, Mode}};
+use interface::{Bindings, Settings};
+
+const USAGE: &str = "Usage: tehtrys [practice | replay <file> | dump <file>]";
 
 fn main() {
-    let mut matrix = Matrix::blank();
-    for col in 0..=6 {
-        matrix[(col, 0).into()] = Some(Color::Green);
-    }
+    let bindings = Bindings::load().unwrap_or_else(|error| {
+        eprintln!("{}, using the default bindings instead", error);
+        Bindings::default()
+    });
+    let settings = Settings { bindings, ..Settings::default() };
 
-    let mut engine = Engine::with_matrix(matrix);
-    engine.DEBUG_test_cursor(PieceKind::T, (5, 19).into());
+    let args = env::args().skip(1).collect::<Vec<_>>();
+    let args = args.iter().map(String::as_str).collect::<Vec<_>>();
+    match args[..] {
+        [] => interface::run(|| Setup::new(rand::random()), settings),
+        // Practice games can be undone, but are marked as such in their results
+        ["practice"] => interface::run(|| Setup {
+            rules: Rules { mode: Mode::Practice, ..Rules::default() },
+            ..Setup::new(rand::random())
+        }, settings),
+        ["replay", path] => interface::play_back(load_or_exit(path), settings),
+        // The JSON form is for poking around in a replay, and can be played back as well
+        ["dump", path] => println!("{}", load_or_exit(path).to_json()),
+        _ => {
+            eprintln!("{}", USAGE);
+            process::exit(2);
+        }
+    }
+}
 
-    interface::run(engine);
+fn load_or_exit(path: &str) -> Replay {
+    interface::load_replay(Path::new(path)).unwrap_or_else(|error| {
+        eprintln!("{}", error);
+        process::exit(1);
+    })
 }